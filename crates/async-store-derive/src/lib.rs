@@ -0,0 +1,52 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+/// Implements `async_store::Observable` for a struct with named fields and adds
+/// an associated `Field` constant per field, named like the field:
+/// `App::jq_input` is a `Field<App, String>`.
+#[proc_macro_derive(Observable)]
+pub fn derive_observable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Error::new_spanned(&input.ident, "Observable needs named fields")
+                    .to_compile_error()
+                    .into()
+            }
+        },
+        _ => {
+            return Error::new_spanned(&input.ident, "Observable can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let names: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let consts = fields.iter().enumerate().map(|(index, f)| {
+        let (vis, ident, ty) = (&f.vis, f.ident.as_ref().unwrap(), &f.ty);
+        let doc = format!("Tracked `{}` field", ident);
+        quote! {
+            #[doc = #doc]
+            #[allow(non_upper_case_globals)]
+            #vis const #ident: ::async_store::Field<Self, #ty> = ::async_store::Field {
+                index: #index,
+                name: stringify!(#ident),
+                get: |s| &s.#ident,
+                get_mut: |s| &mut s.#ident,
+            };
+        }
+    });
+    quote! {
+        impl #impl_generics ::async_store::Observable for #name #ty_generics #where_clause {
+            const FIELDS: &'static [&'static str] = &[#(stringify!(#names)),*];
+        }
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#consts)*
+        }
+    }
+    .into()
+}
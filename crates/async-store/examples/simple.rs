@@ -1,10 +1,4 @@
-use async_std::{
-    future,
-    io::{prelude::BufReadExt, Lines},
-    prelude::*,
-    sync::{Arc, Mutex, MutexGuard},
-    task,
-};
+use async_std::task;
 use async_store::*;
 use std::time::Duration;
 
@@ -19,12 +13,10 @@ enum Action {
 
 fn main() {
     task::block_on(async {
-        let store = Arc::new(Mutex::new(Store::<Counter, Action>::default()));
+        let store = Store::<Counter, Action>::default();
         store
-            .lock()
-            .await
-            .executer(move |state, action| {
-                task::spawn(async move {
+            .handler(move |state, action, _cancel| {
+                spawn(async move {
                     match action {
                         Action::Increment(v) => {
                             //future::ready(1)
@@ -34,27 +26,34 @@ fn main() {
                             //s2.dispatch(Action::Increment(1)).await;
                         }
                     }
-                    None
+                    Ok(None)
                 })
             })
-            .await;
-        store
-            .lock()
             .await
+            .forget();
+        store
             .reaction(
-                |state| Cond::Becomes(state.v > 0 && state.v % 23 == 0),
+                |state| {
+                    Cond::And(vec![
+                        Cond::Becomes(state.v % 23 == 0, true),
+                        Cond::Is(state.v > 0, true),
+                    ])
+                },
                 move |state| {
-                    task::spawn(async move {
+                    spawn(async move {
                         println!("{:?}  [v%23==0]", state.read().await);
                         //store.dispatch(Action::Increment(1));
 
-                        Some(vec![Action::Increment(-(state.read().await.v as i32))])
+                        Ok(Some(vec![Action::Increment(
+                            -(state.read().await.v as i32),
+                        )]))
                     })
                 },
             )
-            .await;
+            .await
+            .forget();
         loop {
-            store.do(Action::Increment(1));
+            store.dispatch(Action::Increment(1));
 
             task::sleep(Duration::from_micros(8)).await;
         }
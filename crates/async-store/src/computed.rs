@@ -0,0 +1,52 @@
+use crate::{eq_boxed, ASAnyBoxed};
+use std::{
+    any::Any,
+    sync::{Arc, Mutex},
+};
+
+/// Value derived from the state and memoized, it is recomputed only when
+/// the key returned by `deps` changes. Clones share the cached value.
+pub struct Computed<S, T> {
+    inner: Arc<Inner<S, T>>,
+}
+struct Inner<S, T> {
+    deps: Box<dyn Fn(&S) -> ASAnyBoxed + Send + Sync>,
+    compute: Box<dyn Fn(&S) -> T + Send + Sync>,
+    cache: Mutex<Option<(ASAnyBoxed, Arc<T>)>>,
+}
+
+impl<S, T> Computed<S, T> {
+    pub fn new<D, K, F>(deps: D, compute: F) -> Self
+    where
+        D: 'static + Fn(&S) -> K + Send + Sync,
+        K: Any + PartialEq + Send + Sync,
+        F: 'static + Fn(&S) -> T + Send + Sync,
+    {
+        Computed {
+            inner: Arc::new(Inner {
+                deps: Box::new(move |s| Box::new(deps(s))),
+                compute: Box::new(compute),
+                cache: Mutex::new(None),
+            }),
+        }
+    }
+    pub fn get(&self, state: &S) -> Arc<T> {
+        let key = (self.inner.deps)(state);
+        if let Some((k, v)) = &*self.inner.cache.lock().unwrap() {
+            if eq_boxed(k, &key) {
+                return v.clone();
+            }
+        }
+        // computed without the lock so other readers aren't held up
+        let v = Arc::new((self.inner.compute)(state));
+        *self.inner.cache.lock().unwrap() = Some((key, v.clone()));
+        v
+    }
+}
+impl<S, T> Clone for Computed<S, T> {
+    fn clone(&self) -> Self {
+        Computed {
+            inner: self.inner.clone(),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::{any::Any, collections::VecDeque};
+
+/// Part of the state kept for one step, its type is only known to the store
+type Snapshot = Box<dyn Any + Send + Sync>;
+/// Puts a snapshot back into the state
+type Restore<S> = Box<dyn Fn(&mut S, Snapshot) + Send + Sync>;
+/// Tells whether two snapshots are equal
+type Same = Box<dyn Fn(&Snapshot, &Snapshot) -> bool + Send + Sync>;
+
+/// Bounded undo and redo stacks of `(action, snapshot)` pairs
+pub(crate) struct History<S, A> {
+    snapshot: Box<dyn Fn(&S) -> Snapshot + Send + Sync>,
+    restore: Restore<S>,
+    /// Set when steps which changed nothing are left out
+    same: Option<Same>,
+    filter: Box<dyn Fn(&A) -> bool + Send + Sync>,
+    limit: usize,
+    past: VecDeque<(A, Snapshot)>,
+    future: Vec<(A, Snapshot)>,
+}
+
+impl<S, A> History<S, A>
+where
+    A: Clone,
+{
+    pub(crate) fn new<F, P, G, R>(limit: usize, filter: F, snapshot: G, restore: R) -> Self
+    where
+        F: 'static + Fn(&A) -> bool + Send + Sync,
+        P: 'static + Send + Sync,
+        G: 'static + Fn(&S) -> P + Send + Sync,
+        R: 'static + Fn(&mut S, P) + Send + Sync,
+    {
+        History {
+            snapshot: Box::new(move |state| Box::new(snapshot(state))),
+            restore: Box::new(move |state, snapshot| {
+                if let Ok(snapshot) = snapshot.downcast::<P>() {
+                    restore(state, *snapshot)
+                }
+            }),
+            same: None,
+            filter: Box::new(filter),
+            limit,
+            past: VecDeque::new(),
+            future: Vec::new(),
+        }
+    }
+    /// Leaves out the actions after which the snapshot is the same as before
+    pub(crate) fn skip_unchanged<P: 'static + PartialEq>(mut self) -> Self {
+        self.same = Some(Box::new(|a, b| {
+            match (a.downcast_ref::<P>(), b.downcast_ref::<P>()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            }
+        }));
+        self
+    }
+    /// Snapshot of the state before `action`, `None` when it isn't recorded
+    pub(crate) fn before(&self, action: &A, state: &S) -> Option<Snapshot> {
+        if self.limit == 0 || !(self.filter)(action) {
+            return None;
+        }
+        Some((self.snapshot)(state))
+    }
+    /// Saves the snapshot `before` was taken for `action` and drops the redo stack,
+    /// `state` is the state after the action
+    pub(crate) fn record(&mut self, action: &A, before: Snapshot, state: &S) {
+        if let Some(same) = &self.same {
+            if same(&before, &(self.snapshot)(state)) {
+                return;
+            }
+        }
+        if self.past.len() == self.limit {
+            self.past.pop_front();
+        }
+        self.past.push_back((action.clone(), before));
+        self.future.clear();
+    }
+    pub(crate) fn undo(&mut self, state: &mut S) -> Option<A> {
+        let (action, prev) = self.past.pop_back()?;
+        let cur = (self.snapshot)(state);
+        (self.restore)(state, prev);
+        self.future.push((action.clone(), cur));
+        Some(action)
+    }
+    pub(crate) fn redo(&mut self, state: &mut S) -> Option<A> {
+        let (action, next) = self.future.pop()?;
+        let cur = (self.snapshot)(state);
+        (self.restore)(state, next);
+        self.past.push_back((action.clone(), cur));
+        Some(action)
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{spawn, JoinHandle, Middleware, ReadonlyState};
+use async_channel::{Receiver, Sender};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Entries waiting for the writer, more are dropped and counted
+const QUEUE: usize = 256;
+
+/// Publishes every action with its duration and the JSON diff of the state
+/// it made, one JSON object per line:
+/// `{"action": .., "took_ms": .., "diff": [{"path": "/a/0", "old": .., "new": ..}]}`
+///
+/// The dispatch loop only queues the action, a background task serializes the
+/// state, diffs it and writes. The state is read when the entry is written, so a
+/// diff can include the changes of actions queued after it. Entries dropped on a
+/// full queue are counted in `"dropped"` of the next one.
+pub struct Inspector<S, A> {
+    tx: Sender<(ReadonlyState<S>, A, Duration)>,
+    /// Sink and queue end until the first action starts the writer
+    writer: Mutex<Option<Writer<S, A>>>,
+    skip: Box<dyn Fn(&A) -> bool + Send + Sync>,
+    dropped: Arc<AtomicUsize>,
+}
+struct Writer<S, A> {
+    sink: Box<dyn Write + Send>,
+    rx: Receiver<(ReadonlyState<S>, A, Duration)>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl<S, A> Inspector<S, A> {
+    pub fn new(sink: impl Write + Send + 'static) -> Self {
+        let (tx, rx) = async_channel::bounded(QUEUE);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        Inspector {
+            tx,
+            writer: Mutex::new(Some(Writer {
+                sink: Box::new(sink),
+                rx,
+                dropped: dropped.clone(),
+            })),
+            skip: Box::new(|_| false),
+            dropped,
+        }
+    }
+    /// Appends to the file at `path`
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Inspector::new(BufWriter::new(file)))
+    }
+    /// Connects to a Unix socket someone listens on, such as `qurl --inspect`.
+    /// A reader stalling for a second drops the entry being written.
+    #[cfg(unix)]
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+        Ok(Inspector::new(stream))
+    }
+    /// Leaves out actions `skip` returns true for, such as mouse moves.
+    /// Their changes show up in the diff of the next published action.
+    pub fn skip<F>(mut self, skip: F) -> Self
+    where
+        F: 'static + Fn(&A) -> bool + Send + Sync,
+    {
+        self.skip = Box::new(skip);
+        self
+    }
+}
+
+impl<S, A> Writer<S, A>
+where
+    S: Serialize + Send + Sync + 'static,
+    A: Serialize + Send + 'static,
+{
+    async fn run(mut self) {
+        // state as of the last written entry, diffs are taken against it
+        let mut last = Value::Null;
+        while let Ok((state, action, took)) = self.rx.recv().await {
+            let state = serde_json::to_value(&*state.read().await);
+            let (state, action) = match (state, serde_json::to_value(&action)) {
+                (Ok(state), Ok(action)) => (state, action),
+                _ => continue,
+            };
+            let mut changes = vec![];
+            diff(String::new(), &last, &state, &mut changes);
+            last = state;
+            let mut entry = json!({
+                "action": action,
+                "took_ms": took.as_secs_f64() * 1000.0,
+                "diff": changes,
+            });
+            let dropped = self.dropped.swap(0, Ordering::SeqCst);
+            if dropped > 0 {
+                entry["dropped"] = json!(dropped);
+            }
+            let sink = &mut self.sink;
+            // the other end may be gone, inspecting is best effort
+            let _ = serde_json::to_writer(&mut *sink, &entry)
+                .map_err(io::Error::from)
+                .and_then(|_| sink.write_all(b"\n"))
+                .and_then(|_| sink.flush());
+        }
+    }
+}
+
+impl<S, A> Middleware<S, A> for Inspector<S, A>
+where
+    S: Serialize + Send + Sync + 'static,
+    A: Serialize + Send + 'static,
+{
+    fn after(&self, state: ReadonlyState<S>, action: A, took: Duration) -> JoinHandle<()> {
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            spawn(writer.run());
+        }
+        if !(self.skip)(&action) && self.tx.try_send((state, action, took)).is_err() {
+            self.dropped.fetch_add(1, Ordering::SeqCst);
+        }
+        spawn(async {})
+    }
+}
+
+/// Collects the changed leaves between `old` and `new`, paths are JSON pointers
+fn diff(path: String, old: &Value, new: &Value, changes: &mut Vec<Value>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (k, v) in new {
+                let path = format!("{}/{}", path, k.replace('~', "~0").replace('/', "~1"));
+                diff(path, old.get(k).unwrap_or(&Value::Null), v, changes);
+            }
+            for (k, v) in old.iter().filter(|(k, _)| !new.contains_key(*k)) {
+                let path = format!("{}/{}", path, k.replace('~', "~0").replace('/', "~1"));
+                diff(path, v, &Value::Null, changes);
+            }
+        }
+        (Value::Array(o), Value::Array(n)) if o.len() == n.len() => {
+            for (i, (o, n)) in o.iter().zip(n).enumerate() {
+                diff(format!("{}/{}", path, i), o, n, changes);
+            }
+        }
+        _ if old != new => changes.push(json!({ "path": path, "old": old, "new": new })),
+        _ => {}
+    }
+}
+
+#[cfg(all(test, feature = "async-std"))]
+mod tests {
+    use super::*;
+    use crate::Store;
+
+    #[derive(Clone, Default)]
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Waits for the sink to hold `n` lines
+    async fn lines(sink: &Sink, n: usize) -> Vec<Value> {
+        for _ in 0..100 {
+            let out = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+            let lines: Vec<Value> = out.lines().map(|l| l.parse().unwrap()).collect();
+            if lines.len() >= n {
+                return lines;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("no entry written")
+    }
+
+    #[test]
+    fn publishes_diffs_in_the_background() {
+        let sink = Sink::default();
+        let store = Store::<u32, u32>::new(0);
+        async_std::task::block_on(async {
+            let handler = store.handler(|state, n, _| {
+                spawn(async move {
+                    *state.write().await += n;
+                    Ok(None)
+                })
+            });
+            handler.await.forget();
+            let inspector = Inspector::new(sink.clone()).skip(|n| *n == 10);
+            store.middleware(inspector).await.forget();
+            store.dispatch(1).await;
+            let first = lines(&sink, 1).await;
+            assert_eq!(first[0]["action"], json!(1));
+            assert_eq!(first[0]["diff"][0]["new"], json!(1));
+            store.dispatch(10).await;
+            store.dispatch(2).await;
+            let lines = lines(&sink, 2).await;
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[1]["action"], json!(2));
+            // the skipped action is part of the next diff
+            assert_eq!(lines[1]["diff"][0]["old"], json!(1));
+            assert_eq!(lines[1]["diff"][0]["new"], json!(13));
+        });
+    }
+}
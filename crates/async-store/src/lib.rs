@@ -1,17 +1,45 @@
-use async_lock::Barrier;
-use async_std::{
-    future::Future,
-    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    task::{self, JoinHandle},
-};
+mod computed;
+mod history;
+#[cfg(feature = "inspector")]
+mod inspector;
+mod middleware;
+mod observable;
+mod policy;
+pub mod rt;
+mod slice;
+#[cfg(feature = "derive")]
+pub use async_store_derive::Observable;
+pub use computed::Computed;
+use history::History;
+#[cfg(feature = "inspector")]
+pub use inspector::Inspector;
+pub use middleware::Middleware;
+use observable::FieldVersions;
+pub use observable::{Field, Observable};
+use policy::Lane;
+pub use policy::{Cancel, Policy};
+pub use rt::{spawn, JoinHandle, TimeoutError};
+pub use slice::{Slice, SliceWriteGuard};
+
+pub use async_lock::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use event_listener::Event;
+// std::time::Instant panics on wasm32-unknown-unknown
+use instant::Instant;
 use std::{
-    any::{Any, TypeId},
-    pin::Pin,
-    process::Output,
+    any::{type_name, Any},
+    collections::HashMap,
+    fmt::{self, Debug},
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 pub struct State<S> {
     state: Arc<RwLock<S>>,
+    version: Arc<AtomicU64>,
+    fields: Arc<FieldVersions>,
 }
 
 pub trait ASAny: Any {
@@ -32,71 +60,179 @@ impl<T: Any> ASAny for T {
     }
 }
 
-/// This wrapper implements PartialEq<dyn Any> which is needed to
-/// Allow MyAny to become a trait object
-///
-pub type ASAnyBoxed = Box<dyn Any + Send + Sync>; //Box<dyn ASAny + Send + Sync>;
+/// Object safe `PartialEq` against `dyn Any`, implemented for every `PartialEq` type.
+/// Values of different types are never equal.
+pub trait ASEq: ASAny + Send + Sync {
+    fn eq_any(&self, other: &dyn Any) -> bool;
+}
+
+impl<T: Any + PartialEq + Send + Sync> ASEq for T {
+    fn eq_any(&self, other: &dyn Any) -> bool {
+        match other.downcast_ref::<T>() {
+            Some(other) => self == other,
+            None => false,
+        }
+    }
+}
+
+/// Type erased value of a condition, only `PartialEq` types can be boxed into it
+pub type ASAnyBoxed = Box<dyn ASEq>;
 pub enum Cond {
     _Changed(ASAnyBoxed),
     _Becomes(ASAnyBoxed, ASAnyBoxed),
+    _Is(ASAnyBoxed, ASAnyBoxed),
+    _And(Vec<Cond>),
+    _Or(Vec<Cond>),
+    _Not(Box<Cond>),
 }
 impl Cond {
     #[allow(non_snake_case)]
-    pub fn Changed<T: Sized + Any + Send + Sync>(val: T) -> Cond
-    where
-        T: PartialEq,
-    {
+    pub fn Changed<T: Any + PartialEq + Send + Sync>(val: T) -> Cond {
         Cond::_Changed(Box::new(val))
     }
     #[allow(non_snake_case)]
-    pub fn Becomes<T: Sized + Any + Send + Sync>(val: T, expectation: T) -> Cond
-    where
-        T: PartialEq,
-    {
+    pub fn Becomes<T: Any + PartialEq + Send + Sync>(val: T, expectation: T) -> Cond {
         Cond::_Becomes(Box::new(val), Box::new(expectation))
     }
-}
-/* trait CondCorrect {}
-pub struct EqAnyW<T: ?Sized>(pub T);
-impl<T: 'static> PartialEq<dyn Any> for EqAnyW<T>
-where
-    T: PartialEq,
-{
-    fn eq(&self, other: &dyn Any) -> bool {
-        match other.downcast_ref::<T>() {
-            Some(a) => &self.0 == a,
-            None => false,
+    /// Holds while `val` equals `expectation`, no transition needed
+    #[allow(non_snake_case)]
+    pub fn Is<T: Any + PartialEq + Send + Sync>(val: T, expectation: T) -> Cond {
+        Cond::_Is(Box::new(val), Box::new(expectation))
+    }
+    #[allow(non_snake_case)]
+    pub fn And(conds: Vec<Cond>) -> Cond {
+        Cond::_And(conds)
+    }
+    #[allow(non_snake_case)]
+    pub fn Or(conds: Vec<Cond>) -> Cond {
+        Cond::_Or(conds)
+    }
+    #[allow(non_snake_case)]
+    pub fn Not(cond: Cond) -> Cond {
+        Cond::_Not(Box::new(cond))
+    }
+    /// Evaluates the tree against `old`, which must come from the same
+    /// condition closure called on the state before the action
+    pub fn fired(&self, old: &Cond) -> bool {
+        match (old, self) {
+            (Cond::_Changed(v_old), Cond::_Changed(v_new)) => !eq_boxed(v_old, v_new),
+            (Cond::_Becomes(v_old, _), Cond::_Becomes(v_new, ex)) => {
+                !eq_boxed(v_old, ex) && eq_boxed(v_new, ex)
+            }
+            (_, Cond::_Is(v_new, ex)) => eq_boxed(v_new, ex),
+            (Cond::_And(old), Cond::_And(new)) => {
+                old.len() == new.len() && new.iter().zip(old).all(|(n, o)| n.fired(o))
+            }
+            (Cond::_Or(old), Cond::_Or(new)) => new.iter().zip(old).any(|(n, o)| n.fired(o)),
+            (Cond::_Not(old), Cond::_Not(new)) => !new.fired(old),
+            _ => false,
         }
     }
-} */
-
+    /// Checks the tree against the current state only, `Changed` never holds
+    pub fn holds(&self) -> bool {
+        match self {
+            Cond::_Changed(_) => false,
+            Cond::_Becomes(v, ex) | Cond::_Is(v, ex) => eq_boxed(v, ex),
+            Cond::_And(conds) => conds.iter().all(Cond::holds),
+            Cond::_Or(conds) => conds.iter().any(Cond::holds),
+            Cond::_Not(cond) => !cond.holds(),
+        }
+    }
+}
+fn eq_boxed(a: &ASAnyBoxed, b: &ASAnyBoxed) -> bool {
+    // deref twice, `Box<dyn ASEq>` is `Any` itself
+    (**a).eq_any((**b).as_any())
+}
 impl<S> State<S> {
     pub fn new(s: S) -> Self {
         State {
             state: Arc::new(RwLock::new(s)),
+            version: Arc::new(AtomicU64::new(0)),
+            fields: Arc::new(FieldVersions::default()),
         }
     }
-    pub fn read(&self) -> impl Future<Output = RwLockReadGuard<S>> {
+    pub fn read(&self) -> impl Future<Output = RwLockReadGuard<'_, S>> {
         self.state.read()
     }
-    pub fn write(&self) -> impl Future<Output = RwLockWriteGuard<S>> {
+    /// Every call bumps [`State::version`], take one guard for related changes.
+    /// Field watchers can't tell what changed and see every field as written.
+    pub fn write(&self) -> impl Future<Output = RwLockWriteGuard<'_, S>> {
+        self.version.fetch_add(1, Ordering::SeqCst);
+        self.fields.bump_untyped();
         self.state.write()
     }
+    /// Number of writes so far, reactions are skipped while it stays the same
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+    /// Changes on every write to `field`, and on every untyped [`State::write`]
+    pub fn field_version<T>(&self, field: Field<S, T>) -> u64 {
+        self.fields.version(field.index)
+    }
+    pub async fn get<T: Clone>(&self, field: Field<S, T>) -> T {
+        field.get(&*self.read().await).clone()
+    }
+    /// Writes `value` to `field` if it differs, returns whether it did
+    pub async fn set<T: PartialEq>(&self, field: Field<S, T>, value: T) -> bool {
+        if *field.get(&*self.read().await) == value {
+            return false;
+        }
+        self.update(field, |v| *v = value).await;
+        true
+    }
+    /// Changes `field` in place, only its watchers see the write
+    pub async fn update<T, R, F>(&self, field: Field<S, T>, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut state = self.state.write().await;
+        self.version.fetch_add(1, Ordering::SeqCst);
+        self.fields.bump(field.index);
+        f(field.get_mut(&mut state))
+    }
+    pub async fn computed<T>(&self, computed: &Computed<S, T>) -> Arc<T> {
+        computed.get(&*self.read().await)
+    }
+    /// Handle to a slice of the state, writing to it bumps [`State::version`]
+    /// without taking the state lock
+    pub async fn slice<T, F>(&self, slice: F) -> Slice<T>
+    where
+        F: FnOnce(&S) -> &Slice<T>,
+    {
+        slice(&*self.read().await)
+            .share()
+            .notifying(self.version.clone())
+    }
 }
 
 impl<S> Clone for State<S> {
     fn clone(&self) -> Self {
         Self {
             state: self.state.clone(),
+            version: self.version.clone(),
+            fields: self.fields.clone(),
         }
     }
 }
 
 pub struct ReadonlyState<S>(State<S>);
 impl<S> ReadonlyState<S> {
-    pub fn read(&self) -> impl Future<Output = RwLockReadGuard<S>> {
+    pub fn read(&self) -> impl Future<Output = RwLockReadGuard<'_, S>> {
         self.0.read()
     }
+    pub async fn computed<T>(&self, computed: &Computed<S, T>) -> Arc<T> {
+        self.0.computed(computed).await
+    }
+    pub async fn get<T: Clone>(&self, field: Field<S, T>) -> T {
+        self.0.get(field).await
+    }
+    /// Snapshot of a slice of the state
+    pub async fn slice<T, F>(&self, slice: F) -> Arc<T>
+    where
+        F: FnOnce(&S) -> &Slice<T>,
+    {
+        slice(&*self.read().await).get()
+    }
 }
 impl<S> Clone for ReadonlyState<S> {
     fn clone(&self) -> Self {
@@ -124,164 +260,1319 @@ where
         T: 'static + Fn(State<S>, Action) -> JoinHandle<Option<Vec<Action>>> + Send + Sync;
     fn new(state: S) -> Arc<Mutex<Store<S, Action>>>;
 } */
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+/// What handlers and reactions return, follow up actions or an error for [`Store::catch`]
+pub type Outcome<A> = Result<Option<Vec<A>>, Error>;
+
+/// Follow up actions nested deeper than [`Store::max_depth`], most likely
+/// reactions and handlers triggering each other forever.
+/// `chain` holds the `(reaction or handler, action it returned)` steps from the root action.
+#[derive(Debug)]
+pub struct LoopError {
+    pub chain: Vec<(&'static str, String)>,
+}
+impl LoopError {
+    fn new<A: Debug>(chain: &[(&'static str, A)]) -> Self {
+        LoopError {
+            chain: chain
+                .iter()
+                .map(|(source, action)| (*source, format!("{:?}", action)))
+                .collect(),
+        }
+    }
+}
+impl fmt::Display for LoopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts = HashMap::new();
+        for (source, _) in &self.chain {
+            *counts.entry(*source).or_insert(0) += 1;
+        }
+        let (source, count) = counts.into_iter().max_by_key(|c| c.1).unwrap_or(("?", 0));
+        write!(
+            f,
+            "actions cascaded {} levels deep, `{}` returned {} of them; last steps:",
+            self.chain.len(),
+            source,
+            count
+        )?;
+        let skip = self.chain.len().saturating_sub(8);
+        for (source, action) in &self.chain[skip..] {
+            write!(f, "\n  {} -> {}", source, action)?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for LoopError {}
+
+/// `(id, condition, effect, condition value the last evaluation saw, effect name)`
 type ReactionsVec<S, A> = Vec<(
-    Box<dyn Fn(RwLockReadGuard<S>) -> Box<dyn Any + Send + Sync> + Send + Sync>,
-    Box<dyn Fn(ReadonlyState<S>) -> JoinHandle<Option<Vec<A>>> + Send + Sync>,
+    usize,
+    Box<dyn Fn(RwLockReadGuard<S>) -> Cond + Send + Sync>,
+    Box<dyn Fn(ReadonlyState<S>) -> JoinHandle<Outcome<A>> + Send + Sync>,
+    Mutex<Cond>,
+    &'static str,
+)>;
+/// `(id, handler, handler name)`
+type HandlersVec<S, A> = Vec<(
+    usize,
+    Box<dyn Fn(State<S>, A, Cancel) -> JoinHandle<Outcome<A>> + Send + Sync>,
+    &'static str,
 )>;
-type HandlersVec<S, A> = Vec<Box<dyn Fn(State<S>, A) -> JoinHandle<Option<Vec<A>>> + Send + Sync>>;
+/// Follow up actions with the name of the reaction or handler which returned them
+type Chain<A> = Vec<(&'static str, A)>;
+type MiddlewaresVec<S, A> = Vec<(usize, Box<dyn Middleware<S, A>>)>;
+/// Tells whether an action is of the kind a policy is set for
+type Kind<A> = Arc<dyn Fn(&A) -> bool + Send + Sync>;
+/// `(id, action kind, policy, state kept for the kind)`
+type PoliciesVec<A> = Vec<(usize, Kind<A>, Policy, Arc<Lane>)>;
+/// `(action kind, policy, state kept for the kind)` an action is admitted by
+type Admission<A> = (Kind<A>, Policy, Arc<Lane>);
+/// `(id, action, token)` of actions whose handlers are running
+type RunningVec<A> = Vec<(usize, A, Cancel)>;
+type SharedHistory<S, A> = Arc<Mutex<History<S, A>>>;
+type CatchersVec<A> = Vec<(usize, Box<dyn Fn(&Error) -> Option<A> + Send + Sync>)>;
+
+/// Unregisters a reaction or handler when dropped or disposed.
+/// Use [`Disposer::forget`] to keep it registered for the store lifetime.
+#[must_use = "dropping a Disposer unregisters right away"]
+pub struct Disposer(Option<Box<dyn FnOnce() + Send + Sync>>);
+impl Disposer {
+    fn new<F: 'static + FnOnce() + Send + Sync>(dispose: F) -> Self {
+        Disposer(Some(Box::new(dispose)))
+    }
+    /// Removes the entry with `id` from one of the store registries
+    fn remove<T>(vec: &Arc<RwLock<Vec<T>>>, id: usize, id_of: fn(&T) -> usize) -> Self
+    where
+        T: 'static + Send + Sync,
+    {
+        let vec = Arc::downgrade(vec);
+        Disposer::new(move || {
+            if let Some(vec) = vec.upgrade() {
+                if let Some(mut v) = vec.try_write() {
+                    v.retain(|e| id_of(e) != id);
+                    return;
+                }
+                spawn(async move { vec.write().await.retain(|e| id_of(e) != id) });
+            }
+        })
+    }
+    pub fn dispose(mut self) {
+        if let Some(dispose) = self.0.take() {
+            dispose()
+        }
+    }
+    pub fn forget(mut self) {
+        self.0 = None;
+    }
+}
+impl Drop for Disposer {
+    fn drop(&mut self) {
+        if let Some(dispose) = self.0.take() {
+            dispose()
+        }
+    }
+}
+
+/// Counts actions which are still being processed, including the ones they triggered
+struct Inflight {
+    count: AtomicUsize,
+    idle: Event,
+}
+impl Inflight {
+    fn new() -> Self {
+        Inflight {
+            count: AtomicUsize::new(0),
+            idle: Event::new(),
+        }
+    }
+    fn start(self: &Arc<Self>) -> Pending {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Pending(self.clone())
+    }
+    async fn idle(&self) {
+        loop {
+            let listener = self.idle.listen();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
+/// One action in flight, counted until dropped
+struct Pending(Arc<Inflight>);
+impl Pending {
+    fn fork(&self) -> Pending {
+        self.0.start()
+    }
+}
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify(usize::MAX);
+        }
+    }
+}
+
+/// Registries shared by the store handles and the dispatch loop
+struct Shared<S, A> {
+    reactions: Arc<RwLock<ReactionsVec<S, A>>>,
+    handlers: Arc<RwLock<HandlersVec<S, A>>>,
+    middlewares: Arc<RwLock<MiddlewaresVec<S, A>>>,
+    catchers: Arc<RwLock<CatchersVec<A>>>,
+    policies: Arc<RwLock<PoliciesVec<A>>>,
+    running: std::sync::Mutex<RunningVec<A>>,
+    history: std::sync::Mutex<Option<SharedHistory<S, A>>>,
+    max_depth: AtomicUsize,
+    next_id: AtomicUsize,
+    inflight: Arc<Inflight>,
+}
+
+/// Actions sent to the dispatch loop
+struct Dispatch<A> {
+    actions: Vec<A>,
+    chain: Chain<A>,
+    /// Counted until the actions and everything they triggered are done
+    pending: Vec<Pending>,
+    /// Counted until the actions themselves are done
+    done: Option<Pending>,
+}
+
+/// Handle to a store, clones share it. One loop task processes the dispatched
+/// actions in the order they were sent, the handles only queue them.
 pub struct Store<S, A>
 where
     S: Any,
     A: Send + Clone,
 {
     pub state: State<S>,
-    reactions: Arc<RwLock<ReactionsVec<S, A>>>,
-    handlers:
-        Arc<RwLock<HandlersVec<S, A>>>,
-    action: core::marker::PhantomData<A>,
+    shared: Arc<Shared<S, A>>,
+    tx: Arc<async_channel::Sender<Dispatch<A>>>,
 }
 
-impl<S, A> Store<S, A>
+impl<S, A> Clone for Store<S, A>
 where
     S: Any,
     A: Send + Clone,
 {
-    pub async fn reaction<C, T>(&mut self, cond: C, effect: T)
+    fn clone(&self) -> Self {
+        Store {
+            state: self.state.clone(),
+            shared: self.shared.clone(),
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<S, A> Store<S, A>
+where
+    S: Any + Send + Sync,
+    A: Send + Clone + Debug + 'static,
+{
+    /// Starts the dispatch loop, it stops once every handle is dropped
+    pub fn new(state: S) -> Self {
+        let (tx, rx) = async_channel::unbounded();
+        let store = Store {
+            state: State::new(state),
+            shared: Arc::new(Shared {
+                reactions: Arc::new(RwLock::new(Vec::new())),
+                handlers: Arc::new(RwLock::new(Vec::new())),
+                middlewares: Arc::new(RwLock::new(Vec::new())),
+                catchers: Arc::new(RwLock::new(Vec::new())),
+                policies: Arc::new(RwLock::new(Vec::new())),
+                running: std::sync::Mutex::new(Vec::new()),
+                history: std::sync::Mutex::new(None),
+                max_depth: AtomicUsize::new(100),
+                next_id: AtomicUsize::new(0),
+                inflight: Arc::new(Inflight::new()),
+            }),
+            tx: Arc::new(tx),
+        };
+        let dispatcher = store.dispatcher();
+        spawn(async move {
+            while let Ok(dispatch) = rx.recv().await {
+                dispatcher.process(dispatch).await;
+            }
+        });
+        store
+    }
+    /// Shows up in a [`LoopError`] under the type name of `effect`,
+    /// use [`Store::reaction_named`] to tell closures apart
+    pub async fn reaction<C, T>(&self, cond: C, effect: T) -> Disposer
     where
         C: 'static + Fn(RwLockReadGuard<S>) -> Cond + Send + Sync,
-        T: 'static + Fn(ReadonlyState<S>) -> JoinHandle<Option<Vec<A>>> + Send + Sync,
+        T: 'static + Fn(ReadonlyState<S>) -> JoinHandle<Outcome<A>> + Send + Sync,
     {
-        self.reactions
+        self.reaction_named(type_name::<T>(), cond, effect).await
+    }
+    /// Same as [`Store::reaction`], `name` shows up in a [`LoopError`]
+    pub async fn reaction_named<C, T>(&self, name: &'static str, cond: C, effect: T) -> Disposer
+    where
+        C: 'static + Fn(RwLockReadGuard<S>) -> Cond + Send + Sync,
+        T: 'static + Fn(ReadonlyState<S>) -> JoinHandle<Outcome<A>> + Send + Sync,
+    {
+        self.register_reaction(cond, effect, name).await.0
+    }
+    /// Registers a reaction and tells whether its condition holds. The condition is
+    /// evaluated under the registry lock, so no reaction pass runs between the two
+    /// and a transition right after the check can't be missed.
+    async fn register_reaction<C, T>(
+        &self,
+        cond: C,
+        effect: T,
+        name: &'static str,
+    ) -> (Disposer, bool)
+    where
+        C: 'static + Fn(RwLockReadGuard<S>) -> Cond + Send + Sync,
+        T: 'static + Fn(ReadonlyState<S>) -> JoinHandle<Outcome<A>> + Send + Sync,
+    {
+        let id = self.next_id();
+        let mut reactions = self.shared.reactions.write().await;
+        let last = cond(self.state.read().await);
+        let holds = last.holds();
+        reactions.push((id, Box::new(cond), Box::new(effect), Mutex::new(last), name));
+        (Disposer::remove(&self.shared.reactions, id, |e| e.0), holds)
+    }
+    /// Reaction to writes of one field, compared by [`State::field_version`]
+    /// so `T` needs neither `PartialEq` nor a copy per check.
+    /// `effect` gets the value as of the reaction run.
+    pub async fn watch<T, E>(&self, field: Field<S, T>, effect: E) -> Disposer
+    where
+        T: 'static + Clone + Send + Sync,
+        E: 'static + Fn(ReadonlyState<S>, T) -> JoinHandle<Outcome<A>> + Send + Sync,
+    {
+        let fields = self.state.fields.clone();
+        let effect = Arc::new(effect);
+        self.reaction_named(
+            field.name,
+            move |_| Cond::Changed(fields.version(field.index)),
+            move |state| {
+                let effect = effect.clone();
+                spawn(async move {
+                    let value = state.get(field).await;
+                    effect(state, value).await
+                })
+            },
+        )
+        .await
+    }
+    /// Shows up in a [`LoopError`] under the type name of `listener`,
+    /// use [`Store::handler_named`] to tell closures apart
+    pub async fn handler<T>(&self, listener: T) -> Disposer
+    where
+        T: 'static + Fn(State<S>, A, Cancel) -> JoinHandle<Outcome<A>> + Send + Sync,
+    {
+        self.handler_named(type_name::<T>(), listener).await
+    }
+    /// Same as [`Store::handler`], `name` shows up in a [`LoopError`]
+    pub async fn handler_named<T>(&self, name: &'static str, listener: T) -> Disposer
+    where
+        T: 'static + Fn(State<S>, A, Cancel) -> JoinHandle<Outcome<A>> + Send + Sync,
+    {
+        let id = self.next_id();
+        self.shared
+            .handlers
             .write()
             .await
-            .push((Box::new(cond), Box::new(effect)));
+            .push((id, Box::new(listener), name));
+        Disposer::remove(&self.shared.handlers, id, |e| e.0)
     }
-    pub async fn handler<T>(&mut self, listener: T)
+    pub async fn middleware<M>(&self, middleware: M) -> Disposer
     where
-        T: 'static + Fn(State<S>, A) -> JoinHandle<Option<Vec<A>>> + Send + Sync,
+        M: 'static + Middleware<S, A>,
     {
-        self.handlers.write().await.push(Box::new(listener))
+        let id = self.next_id();
+        self.shared
+            .middlewares
+            .write()
+            .await
+            .push((id, Box::new(middleware)));
+        Disposer::remove(&self.shared.middlewares, id, |e| e.0)
     }
-    /* pub async fn wait_until<C>(&mut self, cond: C)
+    /// Sets how actions matching `kind` are scheduled against each other,
+    /// the first registered kind matching an action wins.
+    /// Actions with a policy leave the dispatch loop while they wait and run.
+    pub async fn policy<K>(&self, kind: K, policy: Policy) -> Disposer
+    where
+        K: 'static + Fn(&A) -> bool + Send + Sync,
+    {
+        let id = self.next_id();
+        self.shared.policies.write().await.push((
+            id,
+            Arc::new(kind),
+            policy,
+            Arc::new(Lane::default()),
+        ));
+        Disposer::remove(&self.shared.policies, id, |e| e.0)
+    }
+    /// Cancels the running actions `matching`, see [`Cancel`]
+    pub fn cancel<F>(&self, matching: F)
+    where
+        F: Fn(&A) -> bool,
+    {
+        for r in self.shared.running.lock().unwrap().iter() {
+            if matching(&r.1) {
+                r.2.cancel();
+            }
+        }
+    }
+    /// Receives errors returned by handlers and reactions, the action it maps
+    /// an error to is dispatched like any other follow up action
+    pub async fn catch<T>(&self, catcher: T) -> Disposer
+    where
+        T: 'static + Fn(&Error) -> Option<A> + Send + Sync,
+    {
+        let id = self.next_id();
+        self.shared
+            .catchers
+            .write()
+            .await
+            .push((id, Box::new(catcher)));
+        Disposer::remove(&self.shared.catchers, id, |e| e.0)
+    }
+    /// Derives a memoized value from the state, see [`Computed`]
+    pub fn computed<D, K, F, T>(&self, deps: D, compute: F) -> Computed<S, T>
+    where
+        D: 'static + Fn(&S) -> K + Send + Sync,
+        K: Any + PartialEq + Send + Sync,
+        F: 'static + Fn(&S) -> T + Send + Sync,
+    {
+        Computed::new(deps, compute)
+    }
+    /// Snapshots the state before every action passing `filter`, keeping
+    /// the last `limit` steps for [`Store::undo`] and [`Store::redo`].
+    /// Undo swaps in the snapshot whole, so handles taken with [`Slice::share`]
+    /// keep the replaced slices and the snapshots keep every slice value alive.
+    /// Use [`Store::record_history_with`] and [`Slice::restore`] for states with slices.
+    pub fn record_history<F>(&self, limit: usize, filter: F)
+    where
+        S: Clone,
+        F: 'static + Fn(&A) -> bool + Send + Sync,
+    {
+        let history = History::new(limit, filter, S::clone, |state, prev| *state = prev);
+        *self.shared.history.lock().unwrap() = Some(Arc::new(Mutex::new(history)));
+    }
+    /// Same as [`Store::record_history`] but keeps only what `snapshot` takes from
+    /// the state, undo and redo hand it to `restore`. The rest of the state,
+    /// such as sizes or requests in flight, stays as it is. Actions leaving
+    /// the snapshot as it was get no step.
+    pub fn record_history_with<F, P, G, R>(&self, limit: usize, filter: F, snapshot: G, restore: R)
+    where
+        F: 'static + Fn(&A) -> bool + Send + Sync,
+        P: 'static + PartialEq + Send + Sync,
+        G: 'static + Fn(&S) -> P + Send + Sync,
+        R: 'static + Fn(&mut S, P) + Send + Sync,
+    {
+        let history = History::new(limit, filter, snapshot, restore).skip_unchanged::<P>();
+        *self.shared.history.lock().unwrap() = Some(Arc::new(Mutex::new(history)));
+    }
+    /// Follow up actions nested deeper than `depth` (100 by default) are dropped
+    /// and a [`LoopError`] goes to the catchers instead
+    pub fn max_depth(&self, depth: usize) {
+        self.shared.max_depth.store(depth, Ordering::SeqCst);
+    }
+    fn next_id(&self) -> usize {
+        self.shared.next_id.fetch_add(1, Ordering::SeqCst) + 1
+    }
+    fn dispatcher(&self) -> Dispatcher<S, A> {
+        Dispatcher {
+            shared: self.shared.clone(),
+            state: self.state.clone(),
+            tx: Arc::downgrade(&self.tx),
+        }
+    }
+    /// Queues `action`, the handle resolves once it has been processed.
+    /// Handlers must not wait for other actions, they run inside the dispatch loop.
+    pub fn dispatch(&self, action: A) -> JoinHandle<()> {
+        self.send(vec![action], false)
+    }
+    /// Like [`Store::dispatch`] but resolves only after every action returned by handlers
+    /// and reactions, transitively, has finished
+    pub fn dispatch_and_settle(&self, action: A) -> JoinHandle<()> {
+        self.send(vec![action], true)
+    }
+    /// Processes `actions` in order as one change, reactions run once after all of them
+    pub fn dispatch_batch(&self, actions: Vec<A>) -> JoinHandle<()> {
+        self.send(actions, false)
+    }
+    fn send(&self, actions: Vec<A>, settle: bool) -> JoinHandle<()> {
+        let call = Arc::new(Inflight::new());
+        let mut pending = vec![self.shared.inflight.start()];
+        if settle {
+            pending.push(call.start());
+        }
+        // unbounded, fails only when the loop is gone
+        let _ = self.tx.try_send(Dispatch {
+            actions,
+            chain: vec![],
+            pending,
+            done: Some(call.start()),
+        });
+        spawn(async move { call.idle().await })
+    }
+    /// Resolves once no dispatched action is queued or being processed
+    pub fn idle(&self) -> JoinHandle<()> {
+        let inflight = self.shared.inflight.clone();
+        spawn(async move { inflight.idle().await })
+    }
+    /// Restores the state from before the last recorded action and returns it
+    pub fn undo(&self) -> JoinHandle<Option<A>> {
+        self.dispatcher().travel(true)
+    }
+    pub fn redo(&self) -> JoinHandle<Option<A>> {
+        self.dispatcher().travel(false)
+    }
+    /// Resolves the first time `cond` fires, or right away when it already holds
+    pub fn when<C>(&self, cond: C) -> JoinHandle<()>
     where
         C: 'static + Fn(RwLockReadGuard<S>) -> Cond + Send + Sync,
     {
-        let barrier = Arc::new(Barrier::new(2));
-        let barrier2 = barrier.clone();
-        self.effect(cond, move |state| {
-            let b = barrier2.clone();
-            task::spawn(async move {
-                b.wait().await;
-                None
+        spawn(self.clone().until(cond))
+    }
+    /// Same as [`Store::when`] but gives up after `dur`
+    pub fn when_timeout<C>(&self, cond: C, dur: Duration) -> JoinHandle<Result<(), TimeoutError>>
+    where
+        C: 'static + Fn(RwLockReadGuard<S>) -> Cond + Send + Sync,
+    {
+        spawn(rt::timeout(dur, self.clone().until(cond)))
+    }
+    /// Waits for `cond` like [`Store::when`], the reaction it registers is
+    /// removed once the future completes or is dropped
+    async fn until<C>(self, cond: C)
+    where
+        C: 'static + Fn(RwLockReadGuard<S>) -> Cond + Send + Sync,
+    {
+        let (tx, rx) = async_channel::bounded::<()>(1);
+        let effect = move |_| {
+            let tx = tx.clone();
+            spawn(async move {
+                let _ = tx.try_send(());
+                Ok(None)
             })
-        })
-        .await;
-        barrier.wait().await;
-    } */
+        };
+        let (_disposer, holds) = self.register_reaction(cond, effect, "when").await;
+        if !holds {
+            let _ = rx.recv().await;
+        }
+    }
 }
 impl<S, A> Default for Store<S, A>
 where
-    S: Any + Default,
-    A: Send + Clone,
+    S: Any + Default + Send + Sync,
+    A: Send + Clone + Debug + 'static,
 {
     fn default() -> Self {
-        Store::<S, A> {
-            state: State::new(S::default()),
-            reactions: Arc::new(RwLock::new(Vec::new())),
-            handlers: Arc::new(RwLock::new(Vec::new())),
-            action: core::marker::PhantomData,
+        Store::new(S::default())
+    }
+}
+
+/// The dispatch loop side of a store, it holds the sender weakly
+/// so dropping the last handle stops the loop
+struct Dispatcher<S, A> {
+    shared: Arc<Shared<S, A>>,
+    state: State<S>,
+    tx: std::sync::Weak<async_channel::Sender<Dispatch<A>>>,
+}
+
+impl<S, A> Clone for Dispatcher<S, A> {
+    fn clone(&self) -> Self {
+        Dispatcher {
+            shared: self.shared.clone(),
+            state: self.state.clone(),
+            tx: self.tx.clone(),
         }
     }
 }
-pub trait ArcStore<S, A>
+
+impl<S, A> Dispatcher<S, A>
 where
-    S: Any + 'static,
-    A: Send + Clone + 'static,
+    S: Any + Send + Sync,
+    A: Send + Clone + Debug + 'static,
 {
-    fn do(self, action: A) -> JoinHandle<()>;
+    fn send(&self, dispatch: Dispatch<A>) {
+        if let Some(tx) = self.tx.upgrade() {
+            let _ = tx.try_send(dispatch);
+        }
+    }
+    /// Runs the middlewares and handlers of one dispatch inside the loop.
+    /// Actions with a policy are handled in their own task, the rest of the
+    /// dispatch then finishes there too.
+    async fn process(&self, mut dispatch: Dispatch<A>) {
+        let started = Instant::now();
+        let mut batch = Vec::with_capacity(dispatch.actions.len());
+        'actions: for mut action in std::mem::take(&mut dispatch.actions) {
+            for m in self.shared.middlewares.read().await.iter() {
+                match m.1.before(self.state.clone().into(), action).await {
+                    Some(a) => action = a,
+                    None => continue 'actions,
+                }
+            }
+            batch.push(action);
+        }
+        if batch.is_empty() {
+            return;
+        }
+        let version = self.state.version();
+        let mut outcomes = vec![];
+        let mut deferred = vec![];
+        for action in batch.clone() {
+            let policy = self
+                .shared
+                .policies
+                .read()
+                .await
+                .iter()
+                .find(|p| p.1(&action))
+                .map(|p| (p.1.clone(), p.2, p.3.clone()));
+            match policy {
+                None => outcomes.extend(self.handle(action, Cancel::default(), None).await),
+                Some(policy) => {
+                    let ticket = match policy.1 {
+                        Policy::Queue => Some(policy.2.ticket()),
+                        _ => None,
+                    };
+                    let this = self.clone();
+                    deferred.push(spawn(
+                        async move { this.admit(action, policy, ticket).await },
+                    ));
+                }
+            }
+        }
+        dispatch.actions = batch;
+        if deferred.is_empty() {
+            self.finish(dispatch, version, started, outcomes).await;
+            return;
+        }
+        let this = self.clone();
+        spawn(async move {
+            for d in deferred {
+                outcomes.extend(d.await);
+            }
+            this.finish(dispatch, version, started, outcomes).await
+        });
+    }
+    /// Waits for the turn of `action` as its policy says, then handles it
+    async fn admit(
+        &self,
+        action: A,
+        (kind, policy, lane): Admission<A>,
+        ticket: Option<usize>,
+    ) -> Vec<(&'static str, Outcome<A>)> {
+        let cancel = Cancel::default();
+        let _turn = match ticket {
+            Some(ticket) => Some(lane.turn(ticket).await),
+            None => None,
+        };
+        if !lane.hold(policy, &cancel).await {
+            return vec![];
+        }
+        self.handle(action, cancel, Some((&kind, policy))).await
+    }
+    /// Runs the handlers, nothing is returned for a cancelled action
+    async fn handle(
+        &self,
+        action: A,
+        cancel: Cancel,
+        policy: Option<(&Kind<A>, Policy)>,
+    ) -> Vec<(&'static str, Outcome<A>)> {
+        let id = {
+            let mut running = self.shared.running.lock().unwrap();
+            if let Some((kind, policy)) = policy {
+                let mut same = running.iter().filter(|r| kind(&r.1));
+                match policy {
+                    Policy::TakeFirst if same.next().is_some() => return vec![],
+                    Policy::TakeLatest | Policy::Debounce(_) => same.for_each(|r| r.2.cancel()),
+                    _ => {}
+                }
+            }
+            let id = running.iter().map(|r| r.0 + 1).max().unwrap_or(0);
+            running.push((id, action.clone(), cancel.clone()));
+            id
+        };
+        let history = self.shared.history.lock().unwrap().clone();
+        let before = match &history {
+            Some(history) => {
+                let history = history.lock().await;
+                let state = self.state.read().await;
+                history.before(&action, &state)
+            }
+            None => None,
+        };
+        let mut tasks: Vec<(&'static str, JoinHandle<Outcome<A>>)> = vec![];
+        for r in self.shared.handlers.read().await.iter() {
+            tasks.push((r.2, r.1(self.state.clone(), action.clone(), cancel.clone())));
+        }
+        let mut outcomes = vec![];
+        for (source, t) in tasks {
+            let outcome = t.await;
+            if !cancel.is_cancelled() {
+                outcomes.push((source, outcome));
+            }
+        }
+        if let (Some(history), Some(before)) = (history, before) {
+            let mut history = history.lock().await;
+            let state = self.state.read().await;
+            history.record(&action, before, &state);
+        }
+        self.shared.running.lock().unwrap().retain(|r| r.0 != id);
+        outcomes
+    }
+    /// Runs the reactions and `after` middlewares, then queues the follow up actions
+    async fn finish(
+        &self,
+        dispatch: Dispatch<A>,
+        version: u64,
+        started: Instant,
+        mut outcomes: Vec<(&'static str, Outcome<A>)>,
+    ) {
+        let Dispatch {
+            actions: batch,
+            chain,
+            pending,
+            done,
+        } = dispatch;
+        if self.state.version() != version {
+            outcomes.extend(react(&self.shared.reactions, &self.state).await);
+        }
+        let actions = collect(&self.shared.catchers, outcomes).await;
+        for action in batch {
+            for m in self.shared.middlewares.read().await.iter().rev() {
+                m.1.after(self.state.clone().into(), action.clone(), started.elapsed())
+                    .await;
+            }
+        }
+        drop(done);
+        let max_depth = self.shared.max_depth.load(Ordering::SeqCst);
+        for (source, action) in actions {
+            let mut chain = chain.clone();
+            chain.push((source, action.clone()));
+            if chain.len() <= max_depth {
+                self.send(Dispatch {
+                    actions: vec![action],
+                    chain,
+                    pending: pending.iter().map(Pending::fork).collect(),
+                    done: None,
+                });
+                continue;
+            }
+            // what the catchers return starts over from depth 0
+            let error: Error = Box::new(LoopError::new(&chain));
+            for (_, action) in collect(&self.shared.catchers, vec![(source, Err(error))]).await {
+                self.send(Dispatch {
+                    actions: vec![action],
+                    chain: vec![],
+                    pending: pending.iter().map(Pending::fork).collect(),
+                    done: None,
+                });
+            }
+        }
+    }
+    /// Moves the state one step through the history, reactions see it as a regular change
+    fn travel(self, back: bool) -> JoinHandle<Option<A>> {
+        spawn(async move {
+            let history = self.shared.history.lock().unwrap().clone()?;
+            let pending = self.shared.inflight.start();
+            let action = {
+                let mut history = history.lock().await;
+                let mut state = self.state.write().await;
+                match back {
+                    true => history.undo(&mut state),
+                    false => history.redo(&mut state),
+                }
+            };
+            if action.is_some() {
+                let outcomes = react(&self.shared.reactions, &self.state).await;
+                for (source, a) in collect(&self.shared.catchers, outcomes).await {
+                    self.send(Dispatch {
+                        actions: vec![a.clone()],
+                        chain: vec![(source, a)],
+                        pending: vec![pending.fork()],
+                        done: None,
+                    });
+                }
+            }
+            action
+        })
+    }
 }
-impl<S, A> ArcStore<S, A> for &Arc<Mutex<Store<S, A>>>
-where
-    S: Default + Sync + Send + 'static,
-    A: Send + Clone + 'static,
-{
-    fn do(self, action: A) -> JoinHandle<()> {
-        let store = self.clone();
-        do(store, action)
+
+/// Runs reactions whose condition fired since their previous evaluation
+async fn react<S, A>(
+    effects: &RwLock<ReactionsVec<S, A>>,
+    state: &State<S>,
+) -> Vec<(&'static str, Outcome<A>)> {
+    let mut tasks: Vec<(&'static str, JoinHandle<Outcome<A>>)> = vec![];
+    for e in effects.read().await.iter() {
+        // hold the last value while evaluating, so parallel cascades
+        // see the same transition only once
+        let mut v_old = e.3.lock().await;
+        let v_new = e.1(state.read().await);
+        if v_new.fired(&v_old) {
+            tasks.push((e.4, e.2(state.clone().into())));
+        }
+        *v_old = v_new;
+    }
+    let mut outcomes = vec![];
+    for (source, t) in tasks {
+        outcomes.push((source, t.await));
     }
+    outcomes
 }
 
-pub fn do<S, A>(store: Arc<Mutex<Store<S, A>>>, action: A) -> JoinHandle<()>
-where
-    S: Default + Sync + Send + 'static,
-    A: Send + Clone + 'static,
-{
-    task::spawn(async move {
-        let sl = store.lock().await;
-        let disposers = sl.handlers.clone();
-        let effects = sl.reactions.clone();
-        let state = sl.state.clone();
-        drop(sl);
-        let mut actions: Vec<A> = vec![];
-        let mut tasks: Vec<JoinHandle<Option<Vec<A>>>> = vec![];
-        let mut eff_old: Vec<Cond> = vec![];
-
-        for e in effects.read().await.iter() {
-            eff_old.push(e.0(state.clone().read().await));
-        }
-        for r in disposers.read().await.iter() {
-            let r = r(state.clone(), action.clone());
-            tasks.push(r);
-        }
-        for t in tasks {
-            let actions_ = t.await;
-            if let Some(actions_) = actions_ {
-                actions.extend(actions_);
+/// Flattens follow up actions, errors are mapped to actions by the catchers
+async fn collect<A>(
+    catchers: &RwLock<CatchersVec<A>>,
+    outcomes: Vec<(&'static str, Outcome<A>)>,
+) -> Chain<A> {
+    let mut actions = vec![];
+    for (source, outcome) in outcomes {
+        match outcome {
+            Ok(Some(actions_)) => actions.extend(actions_.into_iter().map(|a| (source, a))),
+            Ok(None) => {}
+            Err(e) => {
+                for c in catchers.read().await.iter() {
+                    actions.extend(c.1(&e).map(|a| (source, a)));
+                }
             }
         }
+    }
+    actions
+}
 
-        let mut tasks: Vec<JoinHandle<Option<Vec<A>>>> = vec![];
-        for (i, e) in effects.read().await.iter().enumerate() {
-            let v_new = e.0(state.clone().read().await);
-            match eff_old.get(i) {
-                Some(Cond::_Becomes(v_old, ex)) => match v_new {
-                    Cond::_Becomes(v_new, ex) => {
-                        let _ex = ex.as_any().downcast_ref::<bool>();
-                        let _v = v_new.as_any().downcast_ref::<bool>();
-                        let _o = v_old.as_any().downcast_ref::<bool>();
-                        if _ex.is_some()
-                            && _o.is_some()
-                            && _v.is_some()
-                            && _o.unwrap() != _ex.unwrap()
-                            && _v.unwrap() == _ex.unwrap()
-                        {
-                            tasks.push(e.1(state.clone().into()));
-                        }
-                        /* match _v {
-                            Some(&true) => match _o {
-                                Some(&false) => {
-                                    tasks.push(ef(state.clone().into()));
-                                }
-                                _ => {}
-                            },
-                            _ => {}
-                        } */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fired(old: Cond, new: Cond) -> bool {
+        new.fired(&old)
+    }
+
+    #[test]
+    fn and_fires_when_all_fire() {
+        let cond = |a: u8, b: u8| Cond::And(vec![Cond::Changed(a), Cond::Changed(b)]);
+        assert!(fired(cond(1, 1), cond(2, 2)));
+        assert!(!fired(cond(1, 1), cond(2, 1)));
+        assert!(!fired(cond(1, 1), cond(1, 1)));
+    }
+
+    #[test]
+    fn or_fires_when_any_fires() {
+        let cond = |a: u8, b: u8| Cond::Or(vec![Cond::Becomes(a, 2), Cond::Changed(b)]);
+        assert!(fired(cond(1, 1), cond(2, 1)));
+        assert!(fired(cond(1, 1), cond(1, 2)));
+        assert!(!fired(cond(2, 1), cond(2, 1)));
+        assert!(cond(2, 1).holds());
+        assert!(!cond(1, 1).holds());
+    }
+
+    #[test]
+    fn not_inverts() {
+        let cond = |a: u8| Cond::Not(Cond::Changed(a));
+        assert!(fired(cond(1), cond(1)));
+        assert!(!fired(cond(1), cond(2)));
+        let cond = |a: u8| Cond::Not(Cond::Is(a, 1));
+        assert!(cond(2).holds());
+        assert!(!cond(1).holds());
+    }
+
+    #[test]
+    fn computed_recomputes_only_when_the_key_changes() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let sum = Computed::new(
+            |s: &(u32, u32)| s.0,
+            move |s: &(u32, u32)| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                s.0 * 10
+            },
+        );
+        assert_eq!(*sum.get(&(1, 0)), 10);
+        // the second field isn't part of the key
+        assert_eq!(*sum.get(&(1, 5)), 10);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(*sum.get(&(2, 5)), 20);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        // clones share the cache
+        assert_eq!(*sum.clone().get(&(2, 0)), 20);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn becomes_needs_a_transition() {
+        let cond = |a: u8| Cond::Becomes(a, 2);
+        assert!(fired(cond(1), cond(2)));
+        assert!(!fired(cond(2), cond(2)));
+        assert!(!fired(cond(2), cond(1)));
+        assert!(cond(2).holds());
+        assert!(!Cond::Changed(1u8).holds());
+    }
+
+    #[test]
+    fn is_inside_and_gates_without_a_transition() {
+        let cond = |a: u8, b: u8| Cond::And(vec![Cond::Is(a, 1), Cond::Changed(b)]);
+        assert!(fired(cond(1, 1), cond(1, 2)));
+        assert!(!fired(cond(0, 1), cond(0, 2)));
+        // `Is` holding alone is not enough, the other branch must fire
+        assert!(!fired(cond(0, 1), cond(1, 1)));
+        assert!(!cond(1, 1).holds());
+        let cond = |a: u8, b: u8| Cond::And(vec![Cond::Is(a, 1), Cond::Is(b, 2)]);
+        assert!(cond(1, 2).holds());
+        assert!(!cond(1, 1).holds());
+    }
+
+    #[test]
+    fn and_of_different_lengths_never_fires() {
+        let old = Cond::And(vec![Cond::Changed(1u8)]);
+        let new = Cond::And(vec![Cond::Changed(2u8), Cond::Changed(2u8)]);
+        assert!(!new.fired(&old));
+    }
+
+    #[derive(PartialEq, Clone, Copy, Debug)]
+    enum Mode {
+        Idle,
+        Busy,
+    }
+
+    #[test]
+    fn changed_compares_strings() {
+        let cond = |s: &str| Cond::Changed(s.to_string());
+        assert!(fired(cond("a"), cond("b")));
+        assert!(!fired(cond("a"), cond("a")));
+    }
+
+    #[test]
+    fn changed_and_becomes_compare_enums() {
+        assert!(fired(Cond::Changed(Mode::Idle), Cond::Changed(Mode::Busy)));
+        assert!(!fired(Cond::Changed(Mode::Busy), Cond::Changed(Mode::Busy)));
+        let cond = |m: Mode| Cond::Becomes(m, Mode::Idle);
+        assert!(fired(cond(Mode::Busy), cond(Mode::Idle)));
+        assert!(!fired(cond(Mode::Idle), cond(Mode::Idle)));
+    }
+
+    #[test]
+    fn mixes_value_types_in_one_tree() {
+        let cond = |name: &str, mode: Mode, n: u64| {
+            Cond::Or(vec![
+                Cond::Changed(name.to_string()),
+                Cond::And(vec![Cond::Is(mode, Mode::Busy), Cond::Changed(n)]),
+            ])
+        };
+        assert!(fired(cond("a", Mode::Idle, 0), cond("b", Mode::Idle, 0)));
+        assert!(fired(cond("a", Mode::Busy, 0), cond("a", Mode::Busy, 1)));
+        assert!(!fired(cond("a", Mode::Idle, 0), cond("a", Mode::Idle, 1)));
+    }
+
+    #[test]
+    fn values_of_different_types_differ() {
+        // a closure returning another type than before compares unequal
+        assert!(fired(Cond::Changed(1u8), Cond::Changed(1u16)));
+        let mismatched = Cond::_Is(Box::new(1u8), Box::new(1u16));
+        assert!(!mismatched.holds());
+    }
+
+    #[cfg(feature = "async-std")]
+    fn counter() -> Store<u32, u32> {
+        let store = Store::new(0);
+        let handler = store.handler(|state, n, _| {
+            spawn(async move {
+                *state.write().await += n;
+                Ok(None)
+            })
+        });
+        async_std::task::block_on(handler).forget();
+        store
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn reactions_run_once_per_batch_and_only_after_writes() {
+        use std::sync::atomic::AtomicUsize;
+        let store = Store::<u32, u32>::new(0);
+        let passes = Arc::new(AtomicUsize::new(0));
+        async_std::task::block_on(async {
+            let handler = store.handler(|state, n, _| {
+                spawn(async move {
+                    // 0 leaves the state alone
+                    if n > 0 {
+                        *state.write().await += n;
                     }
-                    _ => {}
+                    Ok(None)
+                })
+            });
+            handler.await.forget();
+            let counted = passes.clone();
+            let reaction = store.reaction(
+                move |s| {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    Cond::Changed(*s)
                 },
-                _ => {}
-            }
+                |_| spawn(async { Ok(None) }),
+            );
+            reaction.await.forget();
+            // once on registration
+            assert_eq!(passes.load(Ordering::SeqCst), 1);
+            store.dispatch_batch(vec![1, 2, 3]).await;
+            assert_eq!(*store.state.read().await, 6);
+            assert_eq!(passes.load(Ordering::SeqCst), 2);
+            store.dispatch(0).await;
+            assert_eq!(passes.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn when_resolves_on_a_transition_or_right_away() {
+        let store = counter();
+        async_std::task::block_on(async {
+            let reached = store.when(|n| Cond::Becomes(*n >= 2, true));
+            store.dispatch(1).await;
+            store.dispatch(1).await;
+            rt::timeout(Duration::from_secs(1), reached).await.unwrap();
+            let held = store.when(|n| Cond::Becomes(*n >= 2, true));
+            rt::timeout(Duration::from_secs(1), held).await.unwrap();
+            assert!(store.shared.reactions.read().await.is_empty());
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn when_timeout_removes_its_reaction() {
+        let store = counter();
+        async_std::task::block_on(async {
+            let waited = store.when_timeout(|n| Cond::Is(*n, 1), Duration::from_millis(10));
+            assert!(waited.await.is_err());
+            assert!(store.shared.reactions.read().await.is_empty());
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn loop_error_names_the_handler() {
+        let store = Store::<u32, u32>::new(0);
+        let (tx, rx) = async_channel::unbounded();
+        async_std::task::block_on(async {
+            store.max_depth(3);
+            let bounce =
+                store.handler_named("bounce", |_, n, _| spawn(async move { Ok(Some(vec![n])) }));
+            bounce.await.forget();
+            let catcher = store.catch(move |e| {
+                let _ = tx.try_send(e.to_string());
+                None
+            });
+            catcher.await.forget();
+            store.dispatch_and_settle(1).await;
+            let error = rx.recv().await.unwrap();
+            assert!(error.contains("`bounce` returned 4"), "{}", error);
+            assert!(error.contains("\n  bounce -> 1"), "{}", error);
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn partial_history_leaves_the_rest() {
+        // (edited, counted) where only the edits are undone
+        let store = Store::<(u32, u32), u32>::new((0, 0));
+        store.record_history_with(10, |_| true, |s| s.0, |s, edited| s.0 = edited);
+        async_std::task::block_on(async {
+            let handler = store.handler(|state, n, _| {
+                spawn(async move {
+                    let mut s = state.write().await;
+                    s.0 += n;
+                    s.1 += 1;
+                    Ok(None)
+                })
+            });
+            handler.await.forget();
+            store.dispatch(1).await;
+            store.dispatch(2).await;
+            // leaves the edits as they are so it gets no step
+            store.dispatch(0).await;
+            assert_eq!(store.undo().await, Some(2));
+            assert_eq!(*store.state.read().await, (1, 3));
+            assert_eq!(store.redo().await, Some(2));
+            assert_eq!(*store.state.read().await, (3, 3));
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn restored_slices_reach_shared_handles() {
+        let slice = Slice::new(vec![1]);
+        let shared = slice.share();
+        let snapshot = slice.clone();
+        async_std::task::block_on(async {
+            slice.write().await.push(2);
+            assert_eq!(*shared.get(), vec![1, 2]);
+            slice.restore(&snapshot);
+            assert_eq!(*shared.get(), vec![1]);
+            assert_eq!(shared.version(), snapshot.version());
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    /// Logs its calls and rewrites actions with `rewrite` in `before`
+    struct Step {
+        name: &'static str,
+        log: Arc<std::sync::Mutex<Vec<String>>>,
+        rewrite: fn(u32) -> Option<u32>,
+    }
+    #[cfg(feature = "async-std")]
+    impl Middleware<u32, u32> for Step {
+        fn before(&self, _: ReadonlyState<u32>, action: u32) -> JoinHandle<Option<u32>> {
+            let rewritten = (self.rewrite)(action);
+            let entry = format!("{} before {} -> {:?}", self.name, action, rewritten);
+            self.log.lock().unwrap().push(entry);
+            spawn(async move { rewritten })
         }
+        fn after(&self, _: ReadonlyState<u32>, action: u32, _: Duration) -> JoinHandle<()> {
+            let entry = format!("{} after {}", self.name, action);
+            self.log.lock().unwrap().push(entry);
+            spawn(async {})
+        }
+    }
 
-        for t in tasks {
-            let actions_ = t.await;
-            if let Some(actions_) = actions_ {
-                actions.extend(actions_);
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn middlewares_wrap_the_handlers() {
+        let store = counter();
+        let log = Arc::new(std::sync::Mutex::new(vec![]));
+        async_std::task::block_on(async {
+            let steps = [
+                (
+                    "drop",
+                    (|n| Some(n).filter(|n| *n != 0)) as fn(u32) -> Option<u32>,
+                ),
+                ("times", |n| Some(n * 10)),
+                ("plus", |n| Some(n + 1)),
+            ];
+            for (name, rewrite) in steps.iter() {
+                let step = Step {
+                    name,
+                    log: log.clone(),
+                    rewrite: *rewrite,
+                };
+                store.middleware(step).await.forget();
             }
+            store.dispatch(2).await;
+            assert_eq!(*store.state.read().await, 21);
+            assert_eq!(
+                *log.lock().unwrap(),
+                [
+                    "drop before 2 -> Some(2)",
+                    "times before 2 -> Some(20)",
+                    "plus before 20 -> Some(21)",
+                    "plus after 21",
+                    "times after 21",
+                    "drop after 21",
+                ]
+            );
+            log.lock().unwrap().clear();
+            // a dropped action still resolves its handle and runs nothing else
+            let dropped = rt::timeout(Duration::from_secs(1), store.dispatch(0));
+            dropped.await.unwrap();
+            assert_eq!(*store.state.read().await, 21);
+            assert_eq!(*log.lock().unwrap(), ["drop before 0 -> None"]);
+        });
+    }
+
+    /// Store whose handler logs `start n`, waits up to `n` ms for a cancel
+    /// and logs `end n` or `cancel n`
+    #[cfg(feature = "async-std")]
+    fn lane(policy: Policy) -> Store<Vec<String>, u64> {
+        let store = Store::new(vec![]);
+        async_std::task::block_on(async {
+            store.policy(|_| true, policy).await.forget();
+            let handler = store.handler(|state, n, cancel| {
+                spawn(async move {
+                    state.write().await.push(format!("start {}", n));
+                    let _ = rt::timeout(Duration::from_millis(n), cancel.cancelled()).await;
+                    let end = if cancel.is_cancelled() {
+                        "cancel"
+                    } else {
+                        "end"
+                    };
+                    state.write().await.push(format!("{} {}", end, n));
+                    Ok(None)
+                })
+            });
+            handler.await.forget();
+        });
+        store
+    }
+
+    /// Polls until `entry` is logged
+    #[cfg(feature = "async-std")]
+    async fn logged(store: &Store<Vec<String>, u64>, entry: &str) {
+        while !store.state.read().await.iter().any(|e| e == entry) {
+            async_std::task::sleep(Duration::from_millis(1)).await;
         }
-        let mut tasks: Vec<JoinHandle<()>> = vec![];
-        for action in actions {
-            tasks.push(do(store.clone(), action));
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn take_latest_cancels_the_running_action() {
+        let store = lane(Policy::TakeLatest);
+        async_std::task::block_on(async {
+            let first = store.dispatch(5000);
+            logged(&store, "start 5000").await;
+            store.dispatch(1).await;
+            first.await;
+            let log = store.state.read().await;
+            assert!(log.contains(&"cancel 5000".to_string()), "{:?}", log);
+            assert!(log.contains(&"end 1".to_string()), "{:?}", log);
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn take_first_drops_the_new_action() {
+        let store = lane(Policy::TakeFirst);
+        async_std::task::block_on(async {
+            let first = store.dispatch(50);
+            logged(&store, "start 50").await;
+            store.dispatch(1).await;
+            first.await;
+            assert_eq!(*store.state.read().await, ["start 50", "end 50"]);
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn queue_runs_one_by_one_in_dispatch_order() {
+        let store = lane(Policy::Queue);
+        async_std::task::block_on(async {
+            let sent: Vec<_> = (1..=5).rev().map(|n| store.dispatch(n)).collect();
+            for handle in sent {
+                handle.await;
+            }
+            let expected: Vec<_> = (1..=5)
+                .rev()
+                .flat_map(|n| vec![format!("start {}", n), format!("end {}", n)])
+                .collect();
+            assert_eq!(*store.state.read().await, expected);
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn debounce_runs_only_the_last_of_a_burst() {
+        let store = lane(Policy::Debounce(Duration::from_millis(50)));
+        async_std::task::block_on(async {
+            let started = Instant::now();
+            let sent: Vec<_> = (1..=3).map(|n| store.dispatch(n)).collect();
+            for handle in sent {
+                handle.await;
+            }
+            assert!(started.elapsed() >= Duration::from_millis(50));
+            // the replaced ones never start
+            assert_eq!(*store.state.read().await, ["start 3", "end 3"]);
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn throttle_runs_the_latest_held_back_action_at_the_period_end() {
+        let store = lane(Policy::Throttle(Duration::from_millis(100)));
+        async_std::task::block_on(async {
+            let started = Instant::now();
+            store.dispatch(1).await;
+            assert!(started.elapsed() < Duration::from_millis(100));
+            // 2 waits for the period and is replaced by 3 meanwhile
+            let sent = vec![store.dispatch(2), store.dispatch(3)];
+            for handle in sent {
+                handle.await;
+            }
+            assert!(started.elapsed() >= Duration::from_millis(100));
+            assert_eq!(
+                *store.state.read().await,
+                ["start 1", "end 1", "start 3", "end 3"]
+            );
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn store_cancel_reaches_the_matching_handlers() {
+        let store = lane(Policy::Parallel);
+        async_std::task::block_on(async {
+            let sent = vec![store.dispatch(5000), store.dispatch(5001)];
+            logged(&store, "start 5000").await;
+            logged(&store, "start 5001").await;
+            store.cancel(|n| *n == 5001);
+            logged(&store, "cancel 5001").await;
+            assert!(!store
+                .state
+                .read()
+                .await
+                .contains(&"cancel 5000".to_string()));
+            store.cancel(|_| true);
+            for handle in sent {
+                handle.await;
+            }
+        });
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn cancelled_resolves_for_every_clone() {
+        let cancel = Cancel::default();
+        let clone = cancel.clone();
+        async_std::task::block_on(async {
+            let waiting = spawn(async move { clone.cancelled().await });
+            assert!(rt::timeout(Duration::from_millis(20), cancel.cancelled())
+                .await
+                .is_err());
+            cancel.cancel();
+            waiting.await;
+            // resolves right away once cancelled
+            cancel.cancelled().await;
+            assert!(cancel.is_cancelled());
+        });
+    }
+
+    /// Runs `future` on the runtime of the enabled feature, stores have to be
+    /// created inside since their loop is spawned there
+    fn block_on<F: Future>(future: F) -> F::Output {
+        #[cfg(feature = "async-std")]
+        return async_std::task::block_on(future);
+        #[cfg(feature = "tokio")]
+        return tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future);
+        #[cfg(feature = "local")]
+        {
+            rt::set_spawner(|task| {
+                async_std::task::spawn(task);
+            });
+            async_std::task::block_on(future)
         }
-    })
+    }
+
+    #[test]
+    fn store_runs_on_the_enabled_runtime() {
+        block_on(async {
+            let store = Store::<u32, u32>::new(0);
+            let handler = store.handler(|state, n, _| {
+                spawn(async move {
+                    *state.write().await += n;
+                    Ok(None)
+                })
+            });
+            handler.await.forget();
+            let reaction = store.reaction(
+                |s| Cond::Becomes(*s, 3),
+                |_| spawn(async { Ok(Some(vec![10])) }),
+            );
+            reaction.await.forget();
+            store.dispatch_and_settle(1).await;
+            store.dispatch_and_settle(2).await;
+            assert_eq!(*store.state.read().await, 13);
+            let never = Cancel::default();
+            let waited = rt::timeout(Duration::from_millis(5), never.cancelled()).await;
+            assert!(waited.is_err());
+        });
+    }
 }
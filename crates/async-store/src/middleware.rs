@@ -0,0 +1,20 @@
+use crate::{spawn, JoinHandle, ReadonlyState};
+use std::time::Duration;
+
+/// Wraps every dispatched action. `before` runs in registration order ahead of
+/// the handlers, `after` runs in reverse order once the reactions have finished.
+pub trait Middleware<S, A>: Send + Sync
+where
+    A: Send + 'static,
+{
+    /// Returns the action to process, rewritten or as is, `None` drops it.
+    /// Processing waits for the returned task, so it can also delay the action.
+    fn before(&self, _state: ReadonlyState<S>, action: A) -> JoinHandle<Option<A>> {
+        spawn(async move { Some(action) })
+    }
+    /// Sees the action handlers got and the resulting state,
+    /// `elapsed` is measured from the first `before` call
+    fn after(&self, _state: ReadonlyState<S>, _action: A, _elapsed: Duration) -> JoinHandle<()> {
+        spawn(async {})
+    }
+}
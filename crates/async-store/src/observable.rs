@@ -0,0 +1,86 @@
+use crate::{Cond, RwLockReadGuard};
+use std::{
+    any::Any,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// State structs with a [`Field`] per field, derive it with `#[derive(Observable)]`
+pub trait Observable {
+    /// Field names in declaration order, [`Field::index`] points into it
+    const FIELDS: &'static [&'static str];
+}
+
+/// Typed key of a state field, like `App::jq_input`.
+/// Writes through [`State::set`](crate::State::set) and [`State::update`](crate::State::update)
+/// bump the version of that field only, see [`Store::watch`](crate::Store::watch).
+pub struct Field<S, T> {
+    pub index: usize,
+    pub name: &'static str,
+    #[doc(hidden)]
+    pub get: fn(&S) -> &T,
+    #[doc(hidden)]
+    pub get_mut: fn(&mut S) -> &mut T,
+}
+
+impl<S, T> Field<S, T> {
+    pub fn get<'a>(&self, state: &'a S) -> &'a T {
+        (self.get)(state)
+    }
+    pub fn get_mut<'a>(&self, state: &'a mut S) -> &'a mut T {
+        (self.get_mut)(state)
+    }
+}
+impl<S, T> Field<S, T>
+where
+    T: 'static + Any + PartialEq + Clone + Send + Sync,
+{
+    /// Cond closure for reactions, `Cond::Changed` of the field
+    pub fn changed(self) -> impl Fn(RwLockReadGuard<S>) -> Cond + Send + Sync {
+        move |s| Cond::Changed(self.get(&s).clone())
+    }
+    /// `Cond::Becomes` of the field
+    pub fn becomes(self, expectation: T) -> impl Fn(RwLockReadGuard<S>) -> Cond + Send + Sync {
+        move |s| Cond::Becomes(self.get(&s).clone(), expectation.clone())
+    }
+    /// `Cond::Is` of the field
+    pub fn is(self, expectation: T) -> impl Fn(RwLockReadGuard<S>) -> Cond + Send + Sync {
+        move |s| Cond::Is(self.get(&s).clone(), expectation.clone())
+    }
+}
+impl<S, T> Clone for Field<S, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<S, T> Copy for Field<S, T> {}
+impl<S, T> fmt::Debug for Field<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Field").field(&self.name).finish()
+    }
+}
+
+/// Write counters per field index, `untyped` counts the plain `State::write` calls
+/// which could have changed any field
+#[derive(Default)]
+pub(crate) struct FieldVersions {
+    untyped: AtomicU64,
+    fields: std::sync::Mutex<Vec<u64>>,
+}
+impl FieldVersions {
+    pub(crate) fn version(&self, index: usize) -> u64 {
+        let field = self.fields.lock().unwrap().get(index).copied();
+        // both only grow, so the sum changes whenever one of them does
+        self.untyped.load(Ordering::SeqCst) + field.unwrap_or_default()
+    }
+    pub(crate) fn bump(&self, index: usize) {
+        let mut fields = self.fields.lock().unwrap();
+        if fields.len() <= index {
+            fields.resize(index + 1, 0);
+        }
+        fields[index] += 1;
+    }
+    pub(crate) fn bump_untyped(&self) {
+        self.untyped.fetch_add(1, Ordering::SeqCst);
+    }
+}
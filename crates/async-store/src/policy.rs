@@ -0,0 +1,131 @@
+use crate::rt;
+use event_listener::Event;
+use instant::Instant;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How a new action is processed while another one of the same kind is running
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Policy {
+    /// Run alongside the others, actions without a policy run one by one in the dispatch loop
+    Parallel,
+    /// Cancel the running ones and start the new action
+    TakeLatest,
+    /// Drop the new action
+    TakeFirst,
+    /// Wait for the running one to finish, in dispatch order
+    Queue,
+    /// Wait until no newer action came for the duration, then run like `TakeLatest`
+    Debounce(Duration),
+    /// Run at most one action per period, the latest one held back runs at its end
+    Throttle(Duration),
+}
+
+/// Cooperative cancellation token handed to handlers. Cancelled handlers
+/// should return early, their follow up actions are dropped anyway.
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<Inner>);
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    event: Event,
+}
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.event.notify(usize::MAX);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+    fn same(&self, other: &Cancel) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+    /// Resolves once cancelled, to race it against the work
+    pub async fn cancelled(&self) {
+        loop {
+            let listener = self.0.event.listen();
+            if self.is_cancelled() {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
+
+/// What a policy keeps between the actions of its kind
+#[derive(Default)]
+pub(crate) struct Lane {
+    /// Tickets handed out to queued actions and the one whose turn it is
+    tickets: AtomicUsize,
+    turn: AtomicUsize,
+    turned: Event,
+    last: std::sync::Mutex<Option<Instant>>,
+    waiting: std::sync::Mutex<Option<Cancel>>,
+}
+
+impl Lane {
+    /// Place of a queued action, taken in the dispatch loop to keep the order
+    pub(crate) fn ticket(&self) -> usize {
+        self.tickets.fetch_add(1, Ordering::SeqCst)
+    }
+    /// Resolves once every earlier ticket is done, the turn passes on
+    /// when the guard is dropped
+    pub(crate) async fn turn(&self, ticket: usize) -> Turn<'_> {
+        loop {
+            let listener = self.turned.listen();
+            if self.turn.load(Ordering::SeqCst) == ticket {
+                return Turn(self);
+            }
+            listener.await;
+        }
+    }
+    /// Holds the action back for debounce and throttle,
+    /// false when a newer action of the kind replaced it meanwhile
+    pub(crate) async fn hold(&self, policy: Policy, cancel: &Cancel) -> bool {
+        let wait = match policy {
+            Policy::Debounce(dur) => dur,
+            Policy::Throttle(dur) => {
+                let mut last = self.last.lock().unwrap();
+                let now = Instant::now();
+                match *last {
+                    Some(at) if now < at + dur => at + dur - now,
+                    _ => {
+                        *last = Some(now);
+                        return true;
+                    }
+                }
+            }
+            _ => return true,
+        };
+        if let Some(replaced) = self.waiting.lock().unwrap().replace(cancel.clone()) {
+            replaced.cancel();
+        }
+        let _ = rt::timeout(wait, cancel.cancelled()).await;
+        let mut waiting = self.waiting.lock().unwrap();
+        if cancel.is_cancelled() {
+            return false;
+        }
+        if matches!(&*waiting, Some(w) if w.same(cancel)) {
+            *waiting = None;
+        }
+        if let Policy::Throttle(_) = policy {
+            *self.last.lock().unwrap() = Some(Instant::now());
+        }
+        true
+    }
+}
+
+pub(crate) struct Turn<'a>(&'a Lane);
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        self.0.turn.fetch_add(1, Ordering::SeqCst);
+        self.0.turned.notify(usize::MAX);
+    }
+}
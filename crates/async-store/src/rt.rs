@@ -0,0 +1,139 @@
+//! Spawning and timeouts of the runtime picked by the crate features:
+//! `async-std` (default), `tokio`, or `local` for single-threaded executors and wasm.
+//! Handlers and reactions should spawn with [`spawn`] to run on any of them.
+
+#[cfg(feature = "async-std")]
+pub use async_std::{
+    future::{timeout, TimeoutError},
+    task::{spawn, JoinHandle},
+};
+
+#[cfg(all(feature = "tokio", not(feature = "async-std")))]
+pub use self::tokio_rt::*;
+#[cfg(all(feature = "tokio", not(feature = "async-std")))]
+mod tokio_rt {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+    pub use tokio::time::error::Elapsed as TimeoutError;
+
+    /// Resolves to the task output, panics of the task are resumed here
+    pub struct JoinHandle<T>(tokio::task::JoinHandle<T>);
+    impl<T> Future for JoinHandle<T> {
+        type Output = T;
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+            match Pin::new(&mut self.0).poll(cx) {
+                Poll::Ready(Ok(v)) => Poll::Ready(v),
+                Poll::Ready(Err(e)) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Poll::Ready(Err(e)) => panic!("{}", e),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+    pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        JoinHandle(tokio::spawn(future))
+    }
+    pub async fn timeout<F: Future>(dur: Duration, f: F) -> Result<F::Output, TimeoutError> {
+        tokio::time::timeout(dur, f).await
+    }
+}
+
+#[cfg(all(feature = "local", not(any(feature = "async-std", feature = "tokio"))))]
+pub use self::local::*;
+#[cfg(all(feature = "local", not(any(feature = "async-std", feature = "tokio"))))]
+mod local {
+    use futures_timer::Delay;
+    use once_cell::sync::OnceCell;
+    use std::{
+        fmt,
+        future::{poll_fn, Future},
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    pub type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+    static SPAWNER: OnceCell<Box<dyn Fn(Task) + Send + Sync>> = OnceCell::new();
+
+    /// Hands tasks to the executor, call it once before using a store.
+    /// On wasm tasks go to `wasm_bindgen_futures::spawn_local` by default.
+    pub fn set_spawner<F>(spawner: F)
+    where
+        F: 'static + Fn(Task) + Send + Sync,
+    {
+        let _ = SPAWNER.set(Box::new(spawner));
+    }
+
+    pub struct JoinHandle<T>(Pin<Box<dyn Future<Output = T> + Send>>);
+    impl<T> Future for JoinHandle<T> {
+        type Output = T;
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+            self.0.as_mut().poll(cx)
+        }
+    }
+    pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (tx, rx) = async_channel::bounded(1);
+        let task = Box::pin(async move {
+            let _ = tx.send(future.await).await;
+        });
+        match SPAWNER.get() {
+            Some(spawner) => spawner(task),
+            None => spawn_default(task),
+        }
+        JoinHandle(Box::pin(async move {
+            rx.recv().await.expect("spawned task panicked")
+        }))
+    }
+    #[cfg(target_arch = "wasm32")]
+    fn spawn_default(task: Task) {
+        wasm_bindgen_futures::spawn_local(task)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn_default(_: Task) {
+        panic!("no spawner for the `local` runtime, see `async_store::rt::set_spawner`")
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct TimeoutError;
+    impl fmt::Display for TimeoutError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            "future has timed out".fmt(f)
+        }
+    }
+    impl std::error::Error for TimeoutError {}
+
+    pub async fn timeout<F: Future>(dur: Duration, f: F) -> Result<F::Output, TimeoutError> {
+        let mut f = Box::pin(f);
+        let mut delay = Delay::new(dur);
+        poll_fn(|cx| {
+            if let Poll::Ready(v) = f.as_mut().poll(cx) {
+                return Poll::Ready(Ok(v));
+            }
+            Pin::new(&mut delay).poll(cx).map(|_| Err(TimeoutError))
+        })
+        .await
+    }
+}
+
+#[cfg(not(any(feature = "async-std", feature = "tokio", feature = "local")))]
+compile_error!("async-store needs one of the `async-std`, `tokio` or `local` features");
+#[cfg(any(
+    all(feature = "async-std", feature = "tokio"),
+    all(feature = "async-std", feature = "local"),
+    all(feature = "tokio", feature = "local"),
+))]
+compile_error!(
+    "only one of the `async-std`, `tokio` or `local` features can be enabled, \
+     turn off the default `async-std` with `default-features = false`"
+);
@@ -0,0 +1,157 @@
+use async_lock::{Mutex, MutexGuard};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Stamps are unique across slices, restoring a snapshot restores its stamp too
+static STAMP: AtomicU64 = AtomicU64::new(0);
+fn stamp() -> u64 {
+    STAMP.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+/// Copy-on-write part of the state with its own lock, a big write to one slice
+/// blocks neither the rest of the state nor the readers of the slice.
+/// `clone` takes a cheap snapshot, [`Slice::share`] a handle to the same slice.
+pub struct Slice<T> {
+    inner: Arc<Inner<T>>,
+    notify: Option<Arc<AtomicU64>>,
+}
+struct Inner<T> {
+    value: std::sync::Mutex<(u64, Arc<T>)>,
+    writer: Mutex<()>,
+}
+
+impl<T> Slice<T> {
+    pub fn new(value: T) -> Self {
+        Self::from_arc(stamp(), Arc::new(value))
+    }
+    fn from_arc(stamp: u64, value: Arc<T>) -> Self {
+        Slice {
+            inner: Arc::new(Inner {
+                value: std::sync::Mutex::new((stamp, value)),
+                writer: Mutex::new(()),
+            }),
+            notify: None,
+        }
+    }
+    /// Current value, never waits for writers
+    pub fn get(&self) -> Arc<T> {
+        self.inner.value.lock().unwrap().1.clone()
+    }
+    /// Changes on every write, use it in conds to react to this slice only
+    pub fn version(&self) -> u64 {
+        self.inner.value.lock().unwrap().0
+    }
+    /// Handle to the same slice, writes through it are seen by every holder
+    pub fn share(&self) -> Self {
+        Slice {
+            inner: self.inner.clone(),
+            notify: self.notify.clone(),
+        }
+    }
+    /// Puts the value of `snapshot`, taken with `clone`, back into this slice so
+    /// every handle from [`Slice::share`] sees it. Meant for the restore step of
+    /// [`Store::record_history_with`](crate::Store::record_history_with),
+    /// a write in progress publishes over it.
+    pub fn restore(&self, snapshot: &Slice<T>) {
+        let (stamp, value) = snapshot.inner.value.lock().unwrap().clone();
+        self.replace(stamp, value);
+    }
+    pub(crate) fn notifying(mut self, version: Arc<AtomicU64>) -> Self {
+        self.notify = Some(version);
+        self
+    }
+    /// Replaces the value without copying the old one
+    pub async fn set(&self, value: T) {
+        let _writer = self.inner.writer.lock().await;
+        self.publish(Arc::new(value));
+    }
+    /// Writers are serialized, the value is copied on the first mutable access
+    /// and published when the guard is dropped
+    pub async fn write(&self) -> SliceWriteGuard<'_, T>
+    where
+        T: Clone,
+    {
+        let writer = self.inner.writer.lock().await;
+        SliceWriteGuard {
+            value: self.get(),
+            changed: false,
+            slice: self,
+            _writer: writer,
+        }
+    }
+    fn publish(&self, value: Arc<T>) {
+        self.replace(stamp(), value);
+    }
+    fn replace(&self, stamp: u64, value: Arc<T>) {
+        *self.inner.value.lock().unwrap() = (stamp, value);
+        if let Some(version) = &self.notify {
+            version.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl<T> Clone for Slice<T> {
+    fn clone(&self) -> Self {
+        let (stamp, value) = self.inner.value.lock().unwrap().clone();
+        Self::from_arc(stamp, value)
+    }
+}
+impl<T: Default> Default for Slice<T> {
+    fn default() -> Self {
+        Slice::new(T::default())
+    }
+}
+impl<T> From<T> for Slice<T> {
+    fn from(value: T) -> Self {
+        Slice::new(value)
+    }
+}
+/// Same value as long as the stamps match, the content is not compared
+impl<T> PartialEq for Slice<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.version() == other.version()
+    }
+}
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Slice<T> {
+    fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        self.get().serialize(serializer)
+    }
+}
+impl<T: fmt::Debug> fmt::Debug for Slice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Slice").field(&self.get()).finish()
+    }
+}
+
+pub struct SliceWriteGuard<'a, T: Clone> {
+    slice: &'a Slice<T>,
+    value: Arc<T>,
+    changed: bool,
+    _writer: MutexGuard<'a, ()>,
+}
+impl<T: Clone> Deref for SliceWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+impl<T: Clone> DerefMut for SliceWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.changed = true;
+        Arc::make_mut(&mut self.value)
+    }
+}
+impl<T: Clone> Drop for SliceWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.changed {
+            self.slice.publish(self.value.clone());
+        }
+    }
+}
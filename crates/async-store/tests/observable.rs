@@ -0,0 +1,84 @@
+#![cfg(all(feature = "derive", feature = "async-std"))]
+
+use async_std::task;
+use async_store::{spawn, Observable, State, Store};
+use std::sync::{Arc, Mutex};
+
+#[derive(Observable, Default, Debug)]
+struct Form {
+    count: u32,
+    pub name: String,
+}
+
+#[test]
+fn derive_adds_a_field_per_struct_field() {
+    assert_eq!(Form::FIELDS, ["count", "name"]);
+    assert_eq!((Form::name.index, Form::name.name), (1, "name"));
+    let mut form = Form::default();
+    *Form::count.get_mut(&mut form) = 3;
+    Form::name.get_mut(&mut form).push('x');
+    assert_eq!(*Form::count.get(&form), 3);
+    assert_eq!(Form::name.get(&form), "x");
+}
+
+#[test]
+fn set_and_update_bump_their_field_only() {
+    task::block_on(async {
+        let state = State::new(Form::default());
+        let versions = |state: &State<Form>| {
+            (
+                state.field_version(Form::count),
+                state.field_version(Form::name),
+            )
+        };
+        let (count, name) = versions(&state);
+        assert!(state.set(Form::count, 1).await);
+        assert_eq!(versions(&state), (count + 1, name));
+        // the same value is no write
+        let version = state.version();
+        assert!(!state.set(Form::count, 1).await);
+        assert_eq!(state.version(), version);
+        let len = state
+            .update(Form::name, |name| {
+                name.push_str("ab");
+                name.len()
+            })
+            .await;
+        assert_eq!(len, 2);
+        assert_eq!(versions(&state), (count + 1, name + 1));
+        assert_eq!(state.get(Form::name).await, "ab");
+        // untyped writes could have changed anything
+        drop(state.write().await);
+        assert_eq!(versions(&state), (count + 2, name + 2));
+    });
+}
+
+#[test]
+fn watch_fires_for_its_own_field() {
+    let seen = Arc::new(Mutex::new(vec![]));
+    task::block_on(async {
+        let store = Store::<Form, bool>::new(Form::default());
+        let handler = store.handler(|state, count, _| {
+            spawn(async move {
+                if count {
+                    state.update(Form::count, |c| *c += 1).await;
+                } else {
+                    state.update(Form::name, |n| n.push('x')).await;
+                }
+                Ok(None)
+            })
+        });
+        handler.await.forget();
+        let watched = seen.clone();
+        let watch = store.watch(Form::count, move |_, count| {
+            watched.lock().unwrap().push(count);
+            spawn(async { Ok(None) })
+        });
+        watch.await.forget();
+        store.dispatch(false).await;
+        store.dispatch(true).await;
+        store.dispatch(false).await;
+        store.dispatch(true).await;
+    });
+    assert_eq!(*seen.lock().unwrap(), [1, 2]);
+}
@@ -1,13 +1,14 @@
 use crossterm::event;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
 pub enum Mouse {
     Left,
     Right,
     Middle,
 }
-#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
 pub enum Key {
     /// Both Enter (or Return) and numpad Enter
     Enter,
@@ -83,7 +84,7 @@ pub enum Key {
 }
 
 /// Represents an key Modifier.
-#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
 pub enum Mod {
     Ctrl(Key),
     Alt(Key),
@@ -120,6 +121,30 @@ impl Key {
     }
 }
 
+impl Key {
+    pub fn is_mouse(&self) -> bool {
+        matches!(
+            self,
+            Key::MouseMove(..)
+                | Key::MouseScroll(..)
+                | Key::MouseDown(..)
+                | Key::MouseUp(..)
+                | Key::MouseDrag(..)
+                | Key::MouseClick(..)
+        )
+    }
+}
+impl Mod {
+    /// The key without its modifier
+    pub fn key(&self) -> Key {
+        match *self {
+            Mod::Ctrl(key) | Mod::Alt(key) | Mod::Shift(key) | Mod::Any(key) | Mod::Clean(key) => {
+                key
+            }
+        }
+    }
+}
+
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -1,14 +1,216 @@
 pub mod Interaction;
+use crate::{
+    request::Request,
+    state::{App, Pane, Panes},
+    AppStore,
+};
+use async_store::{spawn, Disposer, Policy, State};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
 use tui::layout::Rect;
-use Interaction::Mod;
-#[derive(PartialEq, Debug, Clone)]
+use Interaction::{Key, Mod};
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Rect")]
+pub(crate) struct RectDef {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum AppAction {
     /// An input event occurred.
     Interaction(Vec<Mod>),
     /// Resize event occurred.
-    Resize(Rect),
+    Resize(#[serde(with = "RectDef")] Rect),
     /// An tick event occurred.
     Tick,
+    /// The render scheduler drew a frame.
     Rendered,
+    /// Restore the state from before the last undoable action.
+    Undo,
+    Redo,
+    /// A handler or reaction failed, the message goes to the status bar.
+    Error(String),
+    /// Send the request and stream the response into the input pane,
+    /// a new one cancels the running one and so does Esc.
+    Fetch(Request),
     Exit,
 }
+impl AppAction {
+    /// Whether the action comes from outside the store (terminal events and timer),
+    /// those are enough to replay a recorded session
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            AppAction::Interaction(_) | AppAction::Resize(_) | AppAction::Tick
+        )
+    }
+    /// Interaction made of mouse moves only, those are throttled to the frame rate
+    pub fn is_mouse_move(&self) -> bool {
+        match self {
+            AppAction::Interaction(keys) => {
+                keys.iter().all(|k| matches!(k.key(), Key::MouseMove(..)))
+            }
+            _ => false,
+        }
+    }
+    pub fn is_fetch(&self) -> bool {
+        matches!(self, AppAction::Fetch(_))
+    }
+    /// Whether the action may be kept in the undo history, mouse moves and
+    /// the undo/redo keys themselves are not. Keys leaving the edits as they
+    /// were get no step either.
+    pub fn undoable(&self) -> bool {
+        match self {
+            AppAction::Interaction(keys) => {
+                keys.iter().any(|k| !k.key().is_mouse())
+                    && !keys
+                        .iter()
+                        .any(|k| matches!(k, Mod::Ctrl(Key::Char('z')) | Mod::Ctrl(Key::Char('y'))))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Moves the selection of the open cookies panel or deletes the selected cookie
+async fn cookies_key(state: &State<App>, key: Key) {
+    // a closed panel leaves the state and its version alone
+    if state.read().await.panel != Some(Pane::Cookies) {
+        return;
+    }
+    let mut app = state.write().await;
+    let selected = app.cookies_selected;
+    let jar = app.cookies.share();
+    let count = jar.get().cookies.len();
+    app.cookies_selected = match key {
+        Key::Up => selected.saturating_sub(1),
+        Key::Down => (selected + 1).min(count.saturating_sub(1)),
+        _ => {
+            if selected < count {
+                jar.write().await.cookies.remove(selected);
+            }
+            selected.min(count.saturating_sub(2))
+        }
+    };
+    app.dirty.insert(Pane::Cookies);
+}
+
+/// Registers the handlers applying actions to the [`App`](crate::state::App) state.
+/// They don't need a terminal, so recorded sessions can be replayed headless.
+pub async fn register(store: &AppStore) -> Vec<Disposer> {
+    store.record_history_with(100, AppAction::undoable, App::edits, App::restore);
+    let frame_rate = store.state.read().await.options.frame_rate;
+    let throttle = store
+        .policy(AppAction::is_mouse_move, Policy::Throttle(frame_rate))
+        .await;
+    let take_latest = store.policy(AppAction::is_fetch, Policy::TakeLatest).await;
+    let store2 = store.clone();
+    let handler = store
+        .handler_named("app", move |state, action, cancel| {
+            let store = store2.clone();
+            spawn(async move {
+                Ok(match action {
+                    AppAction::Interaction(keys) => {
+                        let mut actions = vec![];
+                        for key in keys {
+                            match key {
+                                Mod::Any(Key::MouseMove(x, y)) => {
+                                    // the pointer is drawn over the panes it leaves and enters
+                                    let moved: Panes = {
+                                        let app = state.read().await;
+                                        [app.mouse_pos, (x, y)]
+                                            .iter()
+                                            .filter_map(|pos| app.pane_at(*pos))
+                                            .collect()
+                                    };
+                                    // field writes, so only the watchers of these two run
+                                    if state.set(App::mouse_pos, (x, y)).await {
+                                        state.update(App::dirty, |d| *d = *d | moved).await;
+                                    }
+                                }
+                                Mod::Any(Key::Esc) => store.cancel(AppAction::is_fetch),
+                                Mod::Ctrl(Key::Char(c @ ('k' | 'r'))) => {
+                                    let panel = match c {
+                                        'k' => Pane::Cookies,
+                                        _ => Pane::Response,
+                                    };
+                                    let mut state = state.write().await;
+                                    state.panel = match state.panel {
+                                        Some(open) if open == panel => None,
+                                        _ => Some(panel),
+                                    };
+                                    state.dirty = Panes::ALL;
+                                }
+                                Mod::Any(key @ (Key::Up | Key::Down | Key::Delete)) => {
+                                    cookies_key(&state, key).await
+                                }
+                                Mod::Ctrl(Key::Char('q')) => {
+                                    state.write().await.running = false;
+                                }
+                                Mod::Ctrl(Key::Char('z')) => actions.push(AppAction::Undo),
+                                Mod::Ctrl(Key::Char('y')) => actions.push(AppAction::Redo),
+                                _ => {}
+                            }
+                        }
+                        if !actions.is_empty() {
+                            Some(actions)
+                        } else {
+                            None
+                        }
+                    }
+                    AppAction::Resize(r) => {
+                        let mut state = state.write().await;
+                        state.size = r;
+                        state.dirty = Panes::ALL;
+                        None
+                    }
+                    AppAction::Undo | AppAction::Redo => {
+                        let moved = match action {
+                            AppAction::Undo => store.undo().await,
+                            _ => store.redo().await,
+                        };
+                        if moved.is_some() {
+                            state.set(App::dirty, Panes::ALL).await;
+                        }
+                        None
+                    }
+                    AppAction::Tick => {
+                        // turns the spinner while a response stalls
+                        if state.read().await.loading.is_some() {
+                            state
+                                .update(App::loading, |loading| {
+                                    if let Some(loading) = loading {
+                                        loading.ticks += 1;
+                                    }
+                                })
+                                .await;
+                            state.update(App::dirty, |d| d.insert(Pane::Status)).await;
+                        }
+                        None
+                    }
+                    AppAction::Rendered => {
+                        state.set(App::last_render_at, Instant::now()).await;
+                        None
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    AppAction::Fetch(request) => {
+                        crate::request::fetch(&state, request, &cancel).await?;
+                        None
+                    }
+                    AppAction::Error(msg) => {
+                        state.set(App::status, Some(msg)).await;
+                        state.update(App::dirty, |d| d.insert(Pane::Status)).await;
+                        None
+                    }
+                    _ => None,
+                })
+            })
+        })
+        .await;
+    let catcher = store.catch(|e| Some(AppAction::Error(e.to_string()))).await;
+    vec![throttle, take_latest, handler, catcher]
+}
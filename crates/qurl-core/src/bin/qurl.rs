@@ -1,20 +1,302 @@
 use anyhow::{anyhow, Result};
-use async_std::{
-    prelude::*,
-    sync::{Arc, Mutex, MutexGuard},
-    task,
+use async_std::task;
+use async_store::{Cancel, Slice, Store};
+use clap::{AppSettings, ArgMatches, Clap, FromArgMatches, IntoApp, ValueHint};
+use qurl_core::{
+    actions::{self, AppAction},
+    inspect,
+    middlewares::{
+        logger::Logger,
+        recorder::{self, Recorder},
+        tui::Tui,
+    },
+    request::{self, CookieJar, Data, FormField, Redirects, Request},
+    state::App,
 };
-use async_store::Store;
-use qurl_core::{actions::AppAction, middlewares::tui::Tui, state::App};
+use std::{convert::TryFrom, io::Read, path::PathBuf, time::Duration};
+
+#[derive(Clap, Debug)]
+#[clap(version = "1.0", author = "Nikolai K.")]
+#[clap(setting = AppSettings::ColoredHelp)]
+struct Opts {
+    /// The URL syntax is protocol-dependent. You'll find a detailed description in RFC 3986.
+    #[clap(name = "URL", value_hint = ValueHint::Url)]
+    url: Option<String>,
+    /// Request method, GET or POST when sending data by default
+    #[clap(short = 'X', long = "request")]
+    method: Option<String>,
+    /// Write the response body to the file as well
+    #[clap(short, long, parse(from_os_str), value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+    /// Extra header to include in the request, can be used multiple times
+    #[clap(short = 'H', long = "header")]
+    headers: Vec<String>,
+    /// Specify the user name and password to use for server authentication.
+    #[clap(name = "user:password", short, long)]
+    user: Option<String>,
+    /// POST data, `@file` reads it from a file with line breaks removed and `@-` from stdin.
+    /// Every data option can be used multiple times, the parts are joined with `&`
+    #[clap(short = 'd', long = "data")]
+    data: Vec<String>,
+    /// POST data without the `@file` handling
+    #[clap(long = "data-raw")]
+    data_raw: Vec<String>,
+    /// POST data, `@file` is sent exactly as it is
+    #[clap(long = "data-binary")]
+    data_binary: Vec<String>,
+    /// POST data URL-encoded: `content`, `=content`, `name=content`, `@file` or `name@file`
+    #[clap(long = "data-urlencode")]
+    data_urlencode: Vec<String>,
+    /// Send the data in the URL query string of a GET request
+    #[clap(short = 'G', long = "get")]
+    get: bool,
+    /// multipart/form-data field: `name=value`, `name=@file` uploads a file, `name=<file`
+    /// reads the value from one. `;type=mime` and `;filename=name` may follow
+    #[clap(short = 'F', long = "form")]
+    form: Vec<String>,
+    /// Cookies to send as `name=value; name2=value2`, or a Netscape cookie file
+    /// to start the session with. Can be used multiple times
+    #[clap(short = 'b', long = "cookie")]
+    cookies: Vec<String>,
+    /// Write the cookies of the session to a Netscape cookie file on exit, `-` for stdout
+    #[clap(short = 'c', long = "cookie-jar", parse(from_os_str))]
+    cookie_jar: Option<PathBuf>,
+    /// Follow redirects, every hop shows in the response panel
+    #[clap(short = 'L', long = "location")]
+    location: bool,
+    /// Redirects followed at most with -L, -1 for no limit
+    #[clap(long = "max-redirs", default_value = "50", allow_hyphen_values = true)]
+    max_redirs: i64,
+    /// Send -u and Authorization headers to the other hosts -L goes to as well
+    #[clap(long = "location-trusted")]
+    location_trusted: bool,
+    /// Keep POST after a 301 redirect instead of turning it into a GET
+    #[clap(long = "post301")]
+    post301: bool,
+    /// Keep POST after a 302 redirect instead of turning it into a GET
+    #[clap(long = "post302")]
+    post302: bool,
+    /// Keep the method after a 303 redirect instead of turning it into a GET
+    #[clap(long = "post303")]
+    post303: bool,
+    /// Print the body and then the template filled with `%{http_code}`, `%{time_total}`
+    /// and the other curl write-out variables instead of opening the UI. `@file` reads it
+    #[clap(short = 'w', long = "write-out", value_name = "format")]
+    write_out: Option<String>,
+    /// time in ms between two ticks when render ui.
+    #[clap(long, default_value = "160")]
+    tick_rate: u64,
+    /// Show what another qurl started with `--inspector <path>` publishes
+    #[clap(long, value_name = "path", parse(from_os_str))]
+    inspect: Option<PathBuf>,
+    /// Publish actions and state changes to a socket `qurl --inspect` listens on, or a log file
+    #[clap(long, value_name = "path", parse(from_os_str))]
+    inspector: Option<PathBuf>,
+    /// Record the terminal input to a JSONL file `--replay` plays back
+    #[clap(long, value_name = "path", parse(from_os_str))]
+    record: Option<PathBuf>,
+    /// Play back a session recorded with `--record` at its recorded pace
+    #[clap(long, value_name = "path", parse(from_os_str))]
+    replay: Option<PathBuf>,
+}
+
+impl Opts {
+    /// The `-b` cookie files merged, like curl a `-b` without `=` names a file
+    fn cookie_jar(&self) -> Result<CookieJar> {
+        let mut jar = CookieJar::default();
+        for path in self.cookies.iter().filter(|c| !c.contains('=')) {
+            jar.cookies.extend(CookieJar::load(path)?.cookies);
+        }
+        Ok(jar)
+    }
+    /// The data options in command line order, the order curl joins them in
+    fn data(&self, matches: &ArgMatches) -> Vec<Data> {
+        let options: [(&str, &Vec<String>, fn(String) -> Data); 4] = [
+            ("data", &self.data, Data::Ascii),
+            ("data-raw", &self.data_raw, Data::Raw),
+            ("data-binary", &self.data_binary, Data::Binary),
+            ("data-urlencode", &self.data_urlencode, Data::UrlEncode),
+        ];
+        // clap names the args after the fields, they are looked up by their flag
+        let app = Opts::into_app();
+        let mut parts: Vec<_> = (options.iter())
+            .flat_map(|(long, values, kind)| {
+                let arg = app.get_arguments().find(|a| a.get_long() == Some(long));
+                let indices = arg.and_then(|a| matches.indices_of(a.get_name()));
+                indices
+                    .into_iter()
+                    .flatten()
+                    .zip(values.iter().cloned().map(*kind))
+            })
+            .collect();
+        parts.sort_by_key(|(index, _)| *index);
+        parts.into_iter().map(|(_, part)| part).collect()
+    }
+    fn request(&self, matches: &ArgMatches) -> Result<Option<Request>> {
+        let url = match &self.url {
+            Some(url) => url.clone(),
+            None => return Ok(None),
+        };
+        let mut headers = self
+            .headers
+            .iter()
+            .map(|h| Request::parse_header(h).ok_or_else(|| anyhow!("bad header {:?}", h)))
+            .collect::<Result<Vec<_>>>()?;
+        let cookies: Vec<_> = (self.cookies.iter())
+            .filter(|c| c.contains('='))
+            .map(String::as_str)
+            .collect();
+        if !cookies.is_empty() {
+            headers.push(("Cookie".to_string(), cookies.join("; ")));
+        }
+        let data = self.data(matches);
+        if !self.form.is_empty() && (!data.is_empty() || self.get) {
+            return Err(anyhow!("-F can't be combined with -d or -G"));
+        }
+        let form = self
+            .form
+            .iter()
+            .map(|f| FormField::parse(f))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let keep_post = [
+            (301, self.post301),
+            (302, self.post302),
+            (303, self.post303),
+        ];
+        let redirects = Redirects {
+            follow: self.location || self.location_trusted,
+            max: u32::try_from(self.max_redirs).ok(),
+            trusted: self.location_trusted,
+            keep_post: (keep_post.iter().filter(|(_, keep)| *keep))
+                .map(|(status, _)| *status)
+                .collect(),
+        };
+        let mut request = Request {
+            url,
+            headers,
+            user: self.user.clone(),
+            output: self.output.clone(),
+            redirects,
+            ..Default::default()
+        }
+        .data(&data, self.get)?
+        .form(&form);
+        if let Some(method) = &self.method {
+            request.method = method.to_uppercase();
+        }
+        Ok(Some(request))
+    }
+}
 
 fn main() -> Result<()> {
-    // Create an application.
-    task::block_on(async {
-        let store = Arc::new(Mutex::new(Store::<App, AppAction>::default()));
-        let _res = Tui::run(store.clone()).await;
-    });
+    let matches = Opts::into_app().get_matches();
+    let opts = Opts::from_arg_matches(&matches).expect("options parsed by clap");
+    if let Some(path) = &opts.inspect {
+        return task::block_on(inspect::watch(path));
+    }
+    if opts.tick_rate >= 1000 {
+        return Err(anyhow!("Tick rate must be below 1000"));
+    }
+    let inspector = opts
+        .inspector
+        .as_ref()
+        .map(inspect::inspector)
+        .transpose()?;
+    let recorder = opts.record.as_ref().map(Recorder::create).transpose()?;
+    let request = opts.request(&matches)?;
+    let cookies = opts.cookie_jar()?;
 
-    // Initialize the terminal user interface.
+    task::block_on(async {
+        let mut app = App::default();
+        app.options.tick_rate = Duration::from_millis(opts.tick_rate);
+        app.cookies = Slice::new(cookies);
+        let store = Store::<App, AppAction>::new(app);
+        store.middleware(Logger::default()).await.forget();
+        if let Some(inspector) = inspector {
+            store.middleware(inspector).await.forget();
+        }
+        if let Some(recorder) = recorder {
+            store.middleware(recorder).await.forget();
+        }
+        let _handlers = actions::register(&store).await;
+        if let Some(path) = opts.replay.clone() {
+            let store = store.clone();
+            task::spawn(async move {
+                if let Err(e) = recorder::replay(&store, &path, true).await {
+                    store.dispatch(AppAction::Error(format!("Replay failed: {}", e)));
+                }
+            });
+        }
+        let res = match &opts.write_out {
+            Some(template) => write_out(&store, request, template).await,
+            None => {
+                if let Some(request) = request {
+                    store.dispatch(AppAction::Fetch(request));
+                }
+                Tui::run(store.clone()).await
+            }
+        };
+        if let Some(path) = &opts.cookie_jar {
+            store.state.read().await.cookies.get().save(path)?;
+        }
+        res
+    })
+}
 
+/// Runs `request` without the UI like curl does, printing the body
+/// unless `-o` took it and then the `-w` template
+async fn write_out(
+    store: &Store<App, AppAction>,
+    request: Option<Request>,
+    template: &str,
+) -> Result<()> {
+    let request = request.ok_or_else(|| anyhow!("-w needs a URL"))?;
+    let template = match template.strip_prefix('@') {
+        Some("-") => {
+            let mut template = String::new();
+            std::io::stdin().read_to_string(&mut template)?;
+            template
+        }
+        Some(path) => std::fs::read_to_string(path)?,
+        None => template.to_string(),
+    };
+    let output = request.output.is_some();
+    request::fetch(&store.state, request, &Cancel::default()).await?;
+    let app = store.state.read().await;
+    if !output {
+        for line in &app.inp_data.get().original_lines {
+            println!("{}", line);
+        }
+    }
+    print!("{}", app.response.write_out(&template));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_options_join_in_command_line_order() {
+        let args = [
+            "qurl",
+            "--data-urlencode",
+            "a b",
+            "-d",
+            "c=1",
+            "--data-raw",
+            "@raw",
+            "--data-binary",
+            "d=2",
+            "-d",
+            "e=3",
+            "http://localhost/",
+        ];
+        let matches = Opts::into_app().try_get_matches_from(args).unwrap();
+        let opts = Opts::from_arg_matches(&matches).unwrap();
+        let request = opts.request(&matches).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        let body = String::from_utf8(request.body.unwrap()).unwrap();
+        assert_eq!(body, "a%20b&c=1&@raw&d=2&e=3");
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{actions::AppAction, state::App};
+use anyhow::Result;
+use async_std::{fs::File, io::BufReader, prelude::*, task};
+use async_store::Inspector;
+use serde_json::Value;
+use std::{path::Path, time::Duration};
+
+/// Publishes the store to `path`, a socket `qurl --inspect` listens on or a log file.
+/// Mouse moves and the render bookkeeping are left out, they come in bursts.
+pub fn inspector(path: impl AsRef<Path>) -> std::io::Result<Inspector<App, AppAction>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if matches!(std::fs::metadata(&path), Ok(m) if m.file_type().is_socket()) {
+            return Ok(Inspector::connect(path)?.skip(noisy));
+        }
+    }
+    Ok(Inspector::file(path)?.skip(noisy))
+}
+
+fn noisy(action: &AppAction) -> bool {
+    action.is_mouse_move() || matches!(action, AppAction::Rendered | AppAction::Tick)
+}
+
+/// Prints what an [`Inspector`] publishes to `path`. An existing file is followed
+/// like `tail -f`, otherwise a Unix socket is created there and every
+/// connecting store is shown in turn.
+pub async fn watch(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    if path.is_file() {
+        return follow(File::open(path).await?).await;
+    }
+    #[cfg(unix)]
+    {
+        use async_std::os::unix::net::UnixListener;
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path).await?;
+        println!("waiting for stores on {}", path.display());
+        let mut incoming = listener.incoming();
+        while let Some(stream) = incoming.next().await {
+            let mut lines = BufReader::new(stream?).lines();
+            println!("-- connected");
+            let mut n = 0;
+            while let Some(line) = lines.next().await {
+                n += 1;
+                print_entry(n, &line?);
+            }
+            println!("-- disconnected");
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    Err(anyhow::anyhow!("{} is not a file", path.display()))
+}
+
+async fn follow(file: File) -> Result<()> {
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    let mut n = 0;
+    loop {
+        if reader.read_line(&mut line).await? == 0 || !line.ends_with('\n') {
+            task::sleep(Duration::from_millis(200)).await;
+            continue;
+        }
+        n += 1;
+        print_entry(n, line.trim_end());
+        line.clear();
+    }
+}
+
+fn print_entry(n: usize, line: &str) {
+    let entry: Value = match serde_json::from_str(line) {
+        Ok(entry) => entry,
+        Err(_) => return,
+    };
+    println!(
+        "#{} {} {:.2}ms",
+        n,
+        short(&entry["action"]),
+        entry["took_ms"].as_f64().unwrap_or_default()
+    );
+    for change in entry["diff"].as_array().into_iter().flatten() {
+        let path = change["path"].as_str().unwrap_or_default();
+        println!(
+            "    {}: {} -> {}",
+            if path.is_empty() { "/" } else { path },
+            short(&change["old"]),
+            short(&change["new"])
+        );
+    }
+}
+
+/// Compact JSON cut to fit a line
+fn short(value: &Value) -> String {
+    let s = value.to_string();
+    match s.char_indices().nth(80) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s,
+    }
+}
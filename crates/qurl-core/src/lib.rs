@@ -1,8 +1,9 @@
-use async_std::sync::{Arc, Mutex, MutexGuard};
 pub mod actions;
+pub mod inspect;
 pub mod middlewares;
+pub mod request;
 pub mod state;
 use actions::AppAction;
 use async_store::Store;
 use state::App;
-pub type AppStore = Arc<Mutex<Store<App, AppAction>>>;
+pub type AppStore = Store<App, AppAction>;
@@ -0,0 +1,24 @@
+use async_store::{spawn, JoinHandle, Middleware, ReadonlyState};
+use std::{fmt::Debug, time::Duration};
+
+/// Logs every dispatched action and how long it took through the `log` crate
+pub struct Logger {
+    pub level: log::Level,
+}
+impl Default for Logger {
+    fn default() -> Self {
+        Logger {
+            level: log::Level::Debug,
+        }
+    }
+}
+
+impl<S, A> Middleware<S, A> for Logger
+where
+    A: Debug + Send + 'static,
+{
+    fn after(&self, _state: ReadonlyState<S>, action: A, elapsed: Duration) -> JoinHandle<()> {
+        log::log!(self.level, "{:?} processed in {:?}", action, elapsed);
+        spawn(async {})
+    }
+}
@@ -1 +1,3 @@
+pub mod logger;
+pub mod recorder;
 pub mod tui;
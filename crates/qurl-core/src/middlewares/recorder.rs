@@ -0,0 +1,85 @@
+use crate::{actions::AppAction, state::App, AppStore};
+use anyhow::Result;
+use async_std::{fs::File, io::BufReader, prelude::*, task};
+use async_store::{spawn, JoinHandle, Middleware, ReadonlyState};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// One line of a recorded session
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entry {
+    /// Milliseconds since the recording started
+    pub at: u64,
+    pub action: AppAction,
+}
+
+/// Appends every dispatched input action to a JSONL file,
+/// see [`AppAction::is_input`] and [`replay`]
+pub struct Recorder {
+    file: Mutex<BufWriter<std::fs::File>>,
+    started: Instant,
+}
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Recorder {
+            file: Mutex::new(BufWriter::new(std::fs::File::create(path)?)),
+            started: Instant::now(),
+        })
+    }
+    fn write(&self, entry: &Entry) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        serde_json::to_writer(&mut *file, entry)?;
+        file.write_all(b"\n")?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Middleware<App, AppAction> for Recorder {
+    fn before(
+        &self,
+        _state: ReadonlyState<App>,
+        action: AppAction,
+    ) -> JoinHandle<Option<AppAction>> {
+        if action.is_input() {
+            let entry = Entry {
+                at: self.started.elapsed().as_millis() as u64,
+                action: action.clone(),
+            };
+            if let Err(e) = self.write(&entry) {
+                log::error!("Failed to record {:?}: {}", action, e);
+            }
+        }
+        spawn(async move { Some(action) })
+    }
+}
+
+/// Dispatches the actions of a recorded session into `store` one by one,
+/// each after the previous one settled, and returns how many were replayed.
+/// With `realtime` the recorded delays between actions are kept.
+pub async fn replay(store: &AppStore, path: impl AsRef<Path>, realtime: bool) -> Result<usize> {
+    let mut lines = BufReader::new(File::open(path.as_ref()).await?).lines();
+    let started = Instant::now();
+    let mut count = 0;
+    while let Some(line) = lines.next().await {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Entry = serde_json::from_str(&line)?;
+        if realtime {
+            let at = Duration::from_millis(entry.at);
+            if let Some(wait) = at.checked_sub(started.elapsed()) {
+                task::sleep(wait).await;
+            }
+        }
+        store.dispatch_and_settle(entry.action).await;
+        count += 1;
+    }
+    Ok(count)
+}
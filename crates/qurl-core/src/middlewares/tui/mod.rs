@@ -1,131 +1,46 @@
 mod events;
-use crate::{
-    actions::{
-        AppAction,
-        Interaction::{Key, Mod, Mouse},
-    },
-    state::App,
-    AppStore,
-};
-use anyhow::{anyhow, Result};
-use async_lock::Barrier;
+mod render;
+use crate::{actions::AppAction, state::App, AppStore};
+use anyhow::Result;
 use async_std::{
-    future,
-    prelude::*,
-    sync::{Arc, Mutex, MutexGuard, RwLock},
-    task::{self, Context, Poll},
+    sync::{Arc, Mutex},
+    task,
 };
-use async_store::{Cond, Store};
 use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use events::InteractionStream;
-use std::{
-    io::{self, Stderr, Stdout},
-    time::Instant,
-};
-use tui::backend::{Backend, CrosstermBackend};
+use render::RenderScheduler;
+use std::io::{self, Stdout};
+use tui::backend::CrosstermBackend;
 use tui::Terminal;
 /// Representation of a terminal user interface.
 ///
 /// It is responsible for setting up the terminal,
 /// initializing the interface and handling the draw events.
-
 pub struct Tui {
     /// Interface to the Terminal.
     terminal: Terminal<CrosstermBackend<Stdout>>,
 }
 
 impl Tui {
-    /// Constructs a new instance of [`Tui`].
+    /// Constructs a new instance of [`Tui`] and runs it until the app stops.
+    /// The store needs the handlers of [`crate::actions::register`] already.
     pub async fn run(store: AppStore) -> Result<()> {
         let backend = CrosstermBackend::new(io::stdout());
         let terminal = Terminal::new(backend)?;
         let tui = Arc::new(Mutex::new(Tui { terminal }));
         let tui2 = tui.clone();
         tui.lock().await.init()?;
-        let barrier = Arc::new(Barrier::new(2));
-
-        let barrier2 = barrier.clone();
         let actions_task = task::spawn(InteractionStream::run(store.clone()));
 
-        store
-            .lock()
-            .await
-            .executer(move |state, action| {
-                task::spawn(async move {
-                    match action {
-                        AppAction::Interaction(keys) => {
-                            let actions = vec![];
-                            for key in keys {
-                                match key {
-                                    Mod::Any(Key::MouseMove(x, y)) => {
-                                        state.write().await.mouse_pos = (x, y);
-                                        state.write().await.need_render = true;
-                                    }
-                                    Mod::Ctrl(Key::Char('q')) => {
-                                        state.write().await.running = false;
-                                    }
-                                    _ => {}
-                                }
-                            }
-                            if actions.len() > 0 {
-                                Some(actions)
-                            } else {
-                                None
-                            }
-                        }
-                        AppAction::Resize(r) => {
-                            state.write().await.need_render = true;
-                            None
-                        }
-                        AppAction::Rendered => {
-                            let mut state = state.write().await;
-                            state.need_render = false;
-                            state.last_render_at = Instant::now();
-                            None
-                        }
-                        _ => None,
-                    }
-                })
-            })
-            .await;
-        store
-            .lock()
-            .await
-            .reaction(
-                |state| Cond::Becomes(state.need_render, true),
-                move |state| {
-                    let tui2 = tui2.clone();
-                    task::spawn(async move {
-                        let app = state.read().await;
-                        tui2.lock()
-                            .await
-                            .terminal
-                            .draw(move |frame| app.render(frame))
-                            .unwrap();
-                        Some(vec![AppAction::Rendered])
-                    })
-                },
-            )
-            .await;
-        store
-            .lock()
-            .await
-            .reaction(
-                |state| Cond::Becomes(state.running, false),
-                move |state| {
-                    let b = barrier2.clone();
-                    task::spawn(async move {
-                        b.wait().await;
-                        None
-                    })
-                },
-            )
-            .await;
+        let render_task = task::spawn(RenderScheduler::run(store.clone(), tui2));
+        let size = tui.lock().await.terminal.size()?;
+        store.dispatch(AppAction::Resize(size));
 
         // Exit the user interface.
-        barrier.wait().await;
+        store.when(App::running.becomes(false)).await;
         let _actions_task = actions_task.await;
+        render_task.await?;
         tui.lock().await.exit()?;
         Ok(())
     }
@@ -0,0 +1,373 @@
+use super::Tui;
+use crate::{
+    actions::AppAction,
+    state::{App, Pane},
+    AppStore,
+};
+use anyhow::Result;
+use async_std::{
+    channel, future,
+    sync::{Arc, Mutex},
+    task,
+};
+use async_store::{spawn, Cond};
+use std::{
+    collections::HashMap,
+    io::Stdout,
+    mem,
+    time::{Duration, Instant},
+};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    buffer::Buffer,
+    layout::Rect,
+    widgets::Widget,
+    Terminal,
+};
+
+/// Where the frames go, the terminal of [`Tui`] or a test backend
+pub trait Screen: Send + 'static {
+    type Backend: Backend;
+    fn terminal(&mut self) -> &mut Terminal<Self::Backend>;
+}
+impl Screen for Tui {
+    type Backend = CrosstermBackend<Stdout>;
+    fn terminal(&mut self) -> &mut Terminal<Self::Backend> {
+        &mut self.terminal
+    }
+}
+
+/// Draws frames when panes change, at most one per `Options::frame_rate`.
+/// Invalidations in between are merged, only the dirty panes are rendered again
+/// and the others are copied from the last frame. Frames changing no visible pane are skipped.
+pub struct RenderScheduler<T> {
+    store: AppStore,
+    tui: Arc<Mutex<T>>,
+    /// Last rendering of each pane
+    panes: HashMap<Pane, Buffer>,
+    /// Versions of the slices as of their last rendering
+    versions: [u64; 4],
+}
+
+impl<T: Screen> RenderScheduler<T> {
+    /// Renders until the app stops running. Reactions wake it right away, and
+    /// [`State::version`](async_store::State::version) is checked once per frame
+    /// for what no reaction sees, like a response streamed into a slice by a running handler.
+    pub async fn run(store: AppStore, tui: Arc<Mutex<T>>) -> Result<()> {
+        let (tx, rx) = channel::bounded(1);
+        let wake = move || {
+            // one pending wake up is enough, the frame takes every change
+            let _ = tx.try_send(());
+            spawn(async { Ok(None) })
+        };
+        let wake2 = wake.clone();
+        let wake3 = wake.clone();
+        let _disposers = [
+            store.watch(App::dirty, move |_, _| wake()).await,
+            store.watch(App::running, move |_, _| wake2()).await,
+            store
+                .reaction_named(
+                    "slices",
+                    |s| Cond::Changed(slice_versions(&s)),
+                    move |_| wake3(),
+                )
+                .await,
+        ];
+        let mut scheduler = RenderScheduler {
+            store,
+            tui,
+            panes: HashMap::new(),
+            versions: [0; 4],
+        };
+        let mut last_frame = None::<Instant>;
+        // state version as of the last check, nothing to draw while it stays
+        let mut checked = None;
+        loop {
+            let state = scheduler.store.state.read().await;
+            if !state.running {
+                break;
+            }
+            let frame_rate = state.options.frame_rate;
+            drop(state);
+            if checked != Some(scheduler.store.state.version()) {
+                if let Some(last_frame) = last_frame {
+                    task::sleep(frame_rate.saturating_sub(last_frame.elapsed())).await;
+                }
+                // taken before the frame, writes during it get the next one
+                checked = Some(scheduler.store.state.version());
+                if scheduler.frame().await? {
+                    last_frame = Some(Instant::now());
+                    scheduler.store.dispatch(AppAction::Rendered);
+                }
+            }
+            let poll = frame_rate.max(Duration::from_millis(1));
+            if let Ok(Err(_)) = future::timeout(poll, rx.recv()).await {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the dirty panes and draws them, returns whether it drew at all
+    async fn frame(&mut self) -> Result<bool> {
+        let state = &self.store.state;
+        // taking nothing would still be a write and another check
+        let mut dirty = state.read().await.dirty;
+        if !dirty.is_empty() {
+            dirty = state.update(App::dirty, mem::take).await;
+        }
+        let app = state.read().await;
+        let versions = slice_versions(&app);
+        for (i, pane) in [Pane::Input, Pane::Output, Pane::Schema, Pane::Cookies]
+            .iter()
+            .enumerate()
+        {
+            if versions[i] != self.versions[i] {
+                dirty.insert(*pane);
+            }
+        }
+        self.versions = versions;
+
+        let layout = app.layout(app.size);
+        let moved = layout.iter().any(|(pane, area)| {
+            self.panes.get(pane).map(|buf| buf.area) != Some(*area) && area.area() > 0
+        });
+        if (dirty & app.visible()).is_empty() && !moved {
+            return Ok(false);
+        }
+        let panes = &mut self.panes;
+        self.tui.lock().await.terminal().draw(|frame| {
+            for (pane, area) in app.layout(frame.size()) {
+                if area.area() == 0 {
+                    panes.remove(&pane);
+                    continue;
+                }
+                let cached = panes.get(&pane).filter(|buf| buf.area == area);
+                if cached.is_none() || dirty.contains(pane) {
+                    let mut buf = Buffer::empty(area);
+                    app.render_pane(pane, area, &mut buf);
+                    panes.insert(pane, buf);
+                }
+                frame.render_widget(Blit(&panes[&pane]), area);
+            }
+            frame.render_widget(Cursor(&app), frame.size());
+        })?;
+        Ok(true)
+    }
+}
+
+fn slice_versions(app: &App) -> [u64; 4] {
+    [
+        app.inp_data.version(),
+        app.out_data.version(),
+        app.schema_data.version(),
+        app.cookies.version(),
+    ]
+}
+
+/// Copies a pane rendered before
+struct Blit<'a>(&'a Buffer);
+impl Widget for Blit<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = area.intersection(self.0.area);
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                *buf.get_mut(x, y) = self.0.get(x, y).clone();
+            }
+        }
+    }
+}
+
+struct Cursor<'a>(&'a App);
+impl Widget for Cursor<'_> {
+    fn render(self, _: Rect, buf: &mut Buffer) {
+        self.0.render_cursor(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Data, Panes};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tui::backend::TestBackend;
+
+    impl Screen for Terminal<TestBackend> {
+        type Backend = TestBackend;
+        fn terminal(&mut self) -> &mut Terminal<TestBackend> {
+            self
+        }
+    }
+
+    fn store(frame_rate: u64) -> AppStore {
+        let mut app = App::default();
+        app.size = Rect::new(0, 0, 80, 24);
+        app.options.frame_rate = Duration::from_millis(frame_rate);
+        AppStore::new(app)
+    }
+
+    fn scheduler(store: &AppStore) -> RenderScheduler<Terminal<TestBackend>> {
+        RenderScheduler {
+            store: store.clone(),
+            tui: Arc::new(Mutex::new(screen())),
+            panes: HashMap::new(),
+            versions: [0; 4],
+        }
+    }
+
+    fn screen() -> Terminal<TestBackend> {
+        Terminal::new(TestBackend::new(80, 24)).unwrap()
+    }
+
+    fn lines(lines: &[&str]) -> Data {
+        Data {
+            original_lines: lines.iter().map(|l| l.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// What the screen shows, row by row
+    async fn shown(tui: &Arc<Mutex<Terminal<TestBackend>>>) -> String {
+        let mut tui = tui.lock().await;
+        let buf = tui.backend_mut().buffer().clone();
+        let mut text = String::new();
+        for y in 0..buf.area.height {
+            for x in 0..buf.area.width {
+                text.push_str(&buf.get(x, y).symbol);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    #[test]
+    fn changes_between_frames_make_one_frame() {
+        task::block_on(async {
+            let store = store(0);
+            let mut scheduler = scheduler(&store);
+            assert!(scheduler.frame().await.unwrap());
+            assert!(!scheduler.frame().await.unwrap());
+            let app = store.state.read().await;
+            app.inp_data.set(lines(&["first"])).await;
+            app.out_data.set(lines(&["second"])).await;
+            drop(app);
+            store
+                .state
+                .update(App::dirty, |d| d.insert(Pane::Query))
+                .await;
+            assert!(scheduler.frame().await.unwrap());
+            let text = shown(&scheduler.tui).await;
+            assert!(
+                text.contains("first") && text.contains("second"),
+                "{}",
+                text
+            );
+            assert!(!scheduler.frame().await.unwrap());
+        });
+    }
+
+    #[test]
+    fn hidden_panes_draw_nothing() {
+        task::block_on(async {
+            let store = store(0);
+            let mut scheduler = scheduler(&store);
+            scheduler.frame().await.unwrap();
+            // the cookies panel is closed
+            assert!(!store.state.read().await.visible().contains(Pane::Cookies));
+            store
+                .state
+                .set(App::dirty, Panes::from(Pane::Cookies))
+                .await;
+            assert!(!scheduler.frame().await.unwrap());
+            assert!(store.state.read().await.dirty.is_empty());
+        });
+    }
+
+    #[test]
+    fn frames_are_capped_at_the_frame_rate() {
+        let frames = Arc::new(AtomicUsize::new(0));
+        task::block_on(async {
+            let store = store(100);
+            let counted = frames.clone();
+            let handler = store.handler(move |state, action, _| {
+                if let AppAction::Rendered = action {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                }
+                spawn(async move {
+                    if let AppAction::Exit = action {
+                        state.set(App::running, false).await;
+                    }
+                    Ok(None)
+                })
+            });
+            handler.await.forget();
+            let running = task::spawn(RenderScheduler::run(
+                store.clone(),
+                Arc::new(Mutex::new(screen())),
+            ));
+            // a write every 5ms for 300ms, no action tells the scheduler about them
+            let inp = store.state.slice(|s| &s.inp_data).await;
+            let started = Instant::now();
+            let mut n = 0;
+            while started.elapsed() < Duration::from_millis(300) {
+                n += 1;
+                inp.set(lines(&[&n.to_string()])).await;
+                task::sleep(Duration::from_millis(5)).await;
+            }
+            store.dispatch(AppAction::Exit).await;
+            running.await.unwrap();
+        });
+        let frames = frames.load(Ordering::SeqCst);
+        // the first frame and about one per 100ms after it
+        assert!((2..=5).contains(&frames), "{} frames", frames);
+    }
+
+    #[test]
+    fn a_streamed_response_shows_before_it_ends() {
+        use crate::{actions, request::Request};
+        use async_std::{io::prelude::*, net::TcpListener};
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let (release, released) = channel::bounded::<()>(1);
+            let server = task::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut head = vec![];
+                let mut byte = [0];
+                while !head.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).await.unwrap();
+                    head.push(byte[0]);
+                }
+                let head = "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n";
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(b"[1,2,").await.unwrap();
+                // the rest waits until the first part is on the screen
+                let _ = released.recv().await;
+                stream.write_all(b"3,4]\n").await.unwrap();
+            });
+            let store = store(0);
+            let _handlers = actions::register(&store).await;
+            let tui = Arc::new(Mutex::new(screen()));
+            let running = task::spawn(RenderScheduler::run(store.clone(), tui.clone()));
+            let request = Request {
+                url: url.clone(),
+                ..Default::default()
+            };
+            let fetching = store.dispatch(AppAction::Fetch(request));
+            let mut text = String::new();
+            for _ in 0..200 {
+                text = shown(&tui).await;
+                if text.contains("[1,2,") && text.contains(&url) {
+                    break;
+                }
+                task::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(text.contains("[1,2,") && text.contains(&url), "{}", text);
+            release.send(()).await.unwrap();
+            fetching.await;
+            server.await;
+            store.state.set(App::running, false).await;
+            running.await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,337 @@
+//! Request bodies from curl's options, `-d` and friends or `-F` fields.
+//! Files are read right away, `@-` reads stdin before the terminal is taken over.
+
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+pub const FORM_URLENCODED: &str = "application/x-www-form-urlencoded";
+
+/// A data option, parts of every kind are joined with `&`
+#[derive(PartialEq, Debug, Clone)]
+pub enum Data {
+    /// `-d`, `@file` is read with its line breaks removed
+    Ascii(String),
+    /// `--data-raw`, `@` has no special meaning
+    Raw(String),
+    /// `--data-binary`, `@file` is read as is
+    Binary(String),
+    /// `--data-urlencode`, `content`, `=content`, `name=content`, `@file` or `name@file`
+    UrlEncode(String),
+}
+
+impl Data {
+    pub fn bytes(&self) -> io::Result<Vec<u8>> {
+        Ok(match self {
+            Data::Ascii(s) => match s.strip_prefix('@') {
+                Some(path) => {
+                    let mut bytes = read(path)?;
+                    bytes.retain(|b| *b != b'\r' && *b != b'\n');
+                    bytes
+                }
+                None => s.as_bytes().to_vec(),
+            },
+            Data::Raw(s) => s.as_bytes().to_vec(),
+            Data::Binary(s) => match s.strip_prefix('@') {
+                Some(path) => read(path)?,
+                None => s.as_bytes().to_vec(),
+            },
+            Data::UrlEncode(s) => {
+                // like curl, a '=' anywhere wins over '@'
+                let (name, content) = if let Some((name, content)) = s.split_once('=') {
+                    (name, content.as_bytes().to_vec())
+                } else if let Some((name, path)) = s.split_once('@') {
+                    (name, read(path)?)
+                } else {
+                    ("", s.as_bytes().to_vec())
+                };
+                let mut encoded = name.as_bytes().to_vec();
+                if !name.is_empty() {
+                    encoded.push(b'=');
+                }
+                encoded.extend(urlencode(&content).bytes());
+                encoded
+            }
+        })
+    }
+}
+
+/// Joins the data options the way curl sends them
+pub fn data(parts: &[Data]) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            body.push(b'&');
+        }
+        body.extend(part.bytes()?);
+    }
+    Ok(body)
+}
+
+/// Percent-encodes everything but the unreserved characters, like `curl_easy_escape`
+pub fn urlencode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (*b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Appends `-G` data to the query string of `url`
+pub fn with_query(url: &str, data: &[u8]) -> String {
+    if data.is_empty() {
+        return url.to_string();
+    }
+    let sep = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", url, sep, String::from_utf8_lossy(data))
+}
+
+/// A `-F` field: `name=value`, `name=@file` to upload a file or `name=<file` for a
+/// value read from a file, followed by `;type=mime` and `;filename=name` as needed
+#[derive(PartialEq, Debug, Clone)]
+pub struct FormField {
+    pub name: String,
+    pub content: Vec<u8>,
+    pub filename: Option<String>,
+    pub mime: Option<String>,
+}
+
+impl FormField {
+    pub fn parse(field: &str) -> io::Result<Self> {
+        let (name, spec) = field.split_once('=').ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("form field {:?} has no '='", field),
+            )
+        })?;
+        let mut params = spec.split(';');
+        let value = params.next().unwrap_or_default();
+        let (mut mime, mut filename) = (None, None);
+        for param in params {
+            match param.trim().split_once('=') {
+                Some(("type", t)) => mime = Some(t.to_string()),
+                Some(("filename", f)) => filename = Some(f.to_string()),
+                _ => {}
+            }
+        }
+        let content = if let Some(path) = value.strip_prefix('@') {
+            if filename.is_none() {
+                filename = Some(
+                    std::path::Path::new(path)
+                        .file_name()
+                        .map_or("-".into(), |f| f.to_string_lossy().into_owned()),
+                );
+            }
+            if mime.is_none() {
+                mime = Some(mime_of(path).to_string());
+            }
+            read(path)?
+        } else if let Some(path) = value.strip_prefix('<') {
+            read(path)?
+        } else {
+            value.as_bytes().to_vec()
+        };
+        Ok(FormField {
+            name: name.to_string(),
+            content,
+            filename,
+            mime,
+        })
+    }
+}
+
+/// `multipart/form-data` body of `fields` and its content type
+pub fn multipart(fields: &[FormField]) -> (String, Vec<u8>) {
+    let boundary = format!("------------------------{:016x}", rand::random::<u64>());
+    let mut body = vec![];
+    for field in fields {
+        body.extend(format!("--{}\r\n", boundary).bytes());
+        body.extend(format!("Content-Disposition: form-data; name=\"{}\"", field.name).bytes());
+        if let Some(filename) = &field.filename {
+            body.extend(format!("; filename=\"{}\"", filename).bytes());
+        }
+        body.extend(b"\r\n");
+        if let Some(mime) = &field.mime {
+            body.extend(format!("Content-Type: {}\r\n", mime).bytes());
+        }
+        body.extend(b"\r\n");
+        body.extend(&field.content);
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{}--\r\n", boundary).bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+/// Contents of `path`, stdin for `-`
+fn read(path: &str) -> io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut bytes = vec![];
+        io::stdin().read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+    fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
+
+/// Upload content type by file extension, curl's short list
+fn mime_of(path: &str) -> &'static str {
+    let ext = path.rsplit('.').next().unwrap_or_default();
+    match ext.to_ascii_lowercase().as_str() {
+        "gif" => "image/gif",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "txt" => "text/plain",
+        "htm" | "html" => "text/html",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes `content` to a file named after the test, removed on drop
+    struct TempFile(PathBuf);
+    impl TempFile {
+        fn new(name: &str, content: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("qurl-{}-{}", std::process::id(), name));
+            fs::write(&path, content).unwrap();
+            TempFile(path)
+        }
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn urlencode_keeps_unreserved_characters() {
+        assert_eq!(urlencode(b"aZ09-._~"), "aZ09-._~");
+        assert_eq!(urlencode("a b&c=d/é".as_bytes()), "a%20b%26c%3Dd%2F%C3%A9");
+    }
+
+    #[test]
+    fn data_reads_files() {
+        let file = TempFile::new("data.txt", b"a=1\r\nb=2\n");
+        let at = format!("@{}", file.path());
+        assert_eq!(Data::Ascii(at.clone()).bytes().unwrap(), b"a=1b=2");
+        assert_eq!(Data::Binary(at.clone()).bytes().unwrap(), b"a=1\r\nb=2\n");
+        assert_eq!(Data::Raw(at.clone()).bytes().unwrap(), at.as_bytes());
+        assert!(Data::Ascii("@/nonexistent/qurl".to_string())
+            .bytes()
+            .is_err());
+    }
+
+    #[test]
+    fn urlencoded_data_forms() {
+        let encode = |s: &str| String::from_utf8(Data::UrlEncode(s.to_string()).bytes().unwrap());
+        assert_eq!(encode("a b").unwrap(), "a%20b");
+        assert_eq!(encode("=a=b").unwrap(), "a%3Db");
+        assert_eq!(encode("name=a b").unwrap(), "name=a%20b");
+        let file = TempFile::new("urlencode.txt", b"x y\n");
+        assert_eq!(encode(&format!("@{}", file.path())).unwrap(), "x%20y%0A");
+        assert_eq!(
+            encode(&format!("name@{}", file.path())).unwrap(),
+            "name=x%20y%0A"
+        );
+        // '=' wins over '@'
+        assert_eq!(encode("a=@b").unwrap(), "a=%40b");
+    }
+
+    #[test]
+    fn data_parts_join_in_order() {
+        let parts = [
+            Data::Ascii("a=1".to_string()),
+            Data::UrlEncode("b=2 3".to_string()),
+            Data::Raw("@c".to_string()),
+        ];
+        assert_eq!(data(&parts).unwrap(), b"a=1&b=2%203&@c");
+    }
+
+    #[test]
+    fn get_moves_data_to_the_query() {
+        let data = b"a=1&b=2";
+        assert_eq!(with_query("http://h/p", data), "http://h/p?a=1&b=2");
+        assert_eq!(with_query("http://h/p?x=0", data), "http://h/p?x=0&a=1&b=2");
+        assert_eq!(with_query("http://h/p", b""), "http://h/p");
+        let request = crate::request::Request {
+            method: "POST".to_string(),
+            url: "http://h/p".to_string(),
+            ..Default::default()
+        };
+        let request = request.data(&[Data::Raw("a=1".to_string())], true).unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.url.as_str()),
+            ("GET", "http://h/p?a=1")
+        );
+        assert_eq!(request.body, None);
+    }
+
+    #[test]
+    fn form_fields() {
+        let field = FormField::parse("name=value;type=text/x").unwrap();
+        assert_eq!(field.content, b"value");
+        assert_eq!(
+            (field.filename, field.mime.as_deref()),
+            (None, Some("text/x"))
+        );
+
+        let file = TempFile::new("upload.json", b"{}");
+        let field = FormField::parse(&format!("doc=@{}", file.path())).unwrap();
+        assert_eq!(field.name, "doc");
+        assert_eq!(field.content, b"{}");
+        let filename = format!("qurl-{}-upload.json", std::process::id());
+        assert_eq!(field.filename.as_deref(), Some(filename.as_str()));
+        assert_eq!(field.mime.as_deref(), Some("application/json"));
+
+        let spec = format!("doc=@{};filename=a.bin;type=x/y", file.path());
+        let field = FormField::parse(&spec).unwrap();
+        assert_eq!(field.filename.as_deref(), Some("a.bin"));
+        assert_eq!(field.mime.as_deref(), Some("x/y"));
+
+        let field = FormField::parse(&format!("text=<{}", file.path())).unwrap();
+        assert_eq!(
+            (field.content, field.filename, field.mime),
+            (b"{}".to_vec(), None, None)
+        );
+
+        assert!(FormField::parse("no-equals").is_err());
+    }
+
+    #[test]
+    fn multipart_layout() {
+        let fields = [
+            FormField::parse("a=1").unwrap(),
+            FormField {
+                name: "f".to_string(),
+                content: b"x".to_vec(),
+                filename: Some("f.txt".to_string()),
+                mime: Some("text/plain".to_string()),
+            },
+        ];
+        let (content_type, body) = multipart(&fields);
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"f\"; filename=\"f.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\nx\r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+    }
+}
@@ -0,0 +1,63 @@
+//! HTTP/1.1 client for surf opening a connection per request, so the phases
+//! curl reports can be timed. Pooled clients hide the lookup and the handshakes.
+
+use async_std::net::{TcpStream, ToSocketAddrs};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use surf::http::{self, url::Host, Version};
+
+/// Time from sending a request to the end of each of its phases,
+/// kept in the extensions of its response
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Phases {
+    pub dns: Duration,
+    pub connect: Duration,
+    /// `None` over plain HTTP
+    pub tls: Option<Duration>,
+    /// Until the response headers were read
+    pub first_byte: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Client;
+
+#[async_trait::async_trait]
+impl surf::HttpClient for Client {
+    async fn send(&self, req: http::Request) -> http::Result<http::Response> {
+        let started = Instant::now();
+        let url = req.url().clone();
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addr = match url.host() {
+            Some(Host::Domain(domain)) => (domain, port).to_socket_addrs().await?.next(),
+            Some(Host::Ipv4(ip)) => Some(SocketAddr::new(ip.into(), port)),
+            Some(Host::Ipv6(ip)) => Some(SocketAddr::new(ip.into(), port)),
+            None => None,
+        }
+        .ok_or_else(|| http::format_err!("can't resolve {}", url))?;
+        let dns = started.elapsed();
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let connect = started.elapsed();
+        let (mut res, tls) = match url.scheme() {
+            "http" => (async_h1::connect(stream, req).await?, None),
+            "https" => {
+                let host = url.host_str().unwrap_or_default();
+                let stream = async_native_tls::connect(host, stream).await?;
+                let tls = started.elapsed();
+                (async_h1::connect(stream, req).await?, Some(tls))
+            }
+            scheme => http::bail!("unsupported scheme {}", scheme),
+        };
+        // async-h1 only accepts HTTP/1.1 responses
+        res.set_version(Some(Version::Http1_1));
+        res.ext_mut().insert(Phases {
+            dns,
+            connect,
+            tls,
+            first_byte: started.elapsed(),
+        });
+        Ok(res)
+    }
+}
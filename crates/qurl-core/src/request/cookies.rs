@@ -0,0 +1,338 @@
+//! Cookie jar kept in [`App::cookies`](crate::state::App::cookies) for the session,
+//! read and written in curl's Netscape cookie file format for `-b` and `-c`.
+
+use async_store::Slice;
+use serde::Serialize;
+use std::{
+    fmt::Write,
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct Cookie {
+    /// Without the leading dot
+    pub domain: String,
+    /// Whether subdomains of `domain` get it too
+    pub tailmatch: bool,
+    pub path: String,
+    pub secure: bool,
+    pub http_only: bool,
+    /// Unix time, 0 for session cookies
+    pub expires: u64,
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookie {
+    fn matches(&self, host: &str, path: &str, https: bool, now: u64) -> bool {
+        let domain = host == self.domain
+            || (self.tailmatch
+                && host.ends_with(&self.domain)
+                && host[..host.len() - self.domain.len()].ends_with('.'));
+        let path = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        domain && path && (https || !self.secure) && !self.expired(now)
+    }
+    fn expired(&self, now: u64) -> bool {
+        self.expires != 0 && self.expires <= now
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
+pub struct CookieJar {
+    pub cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// Reads a Netscape cookie file, a missing one is an empty jar like for curl
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(CookieJar::parse(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CookieJar::default()),
+            Err(e) => Err(e),
+        }
+    }
+    /// Writes the Netscape cookie file, `-` writes to stdout
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if path == Path::new("-") {
+            print!("{}", self);
+            return Ok(());
+        }
+        fs::write(path, self.to_string())
+    }
+    /// Netscape cookie file lines, malformed ones are skipped
+    pub fn parse(text: &str) -> Self {
+        let cookies = text
+            .lines()
+            .filter_map(|line| {
+                let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                    Some(line) => (line, true),
+                    None if line.starts_with('#') => return None,
+                    None => (line, false),
+                };
+                let fields: Vec<_> = line.trim_end_matches('\r').split('\t').collect();
+                if fields.len() != 7 {
+                    return None;
+                }
+                Some(Cookie {
+                    domain: fields[0].trim_start_matches('.').to_string(),
+                    tailmatch: fields[1] == "TRUE",
+                    path: fields[2].to_string(),
+                    secure: fields[3] == "TRUE",
+                    http_only,
+                    expires: fields[4].parse().ok()?,
+                    name: fields[5].to_string(),
+                    value: fields[6].to_string(),
+                })
+            })
+            .collect();
+        CookieJar { cookies }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CookieJar {
+    /// `Cookie` header value for a request to `url`
+    pub fn header(&self, url: &surf::http::Url) -> Option<String> {
+        let host = url.host_str()?;
+        let now = now();
+        let cookies: Vec<_> = self
+            .cookies
+            .iter()
+            .filter(|c| c.matches(host, url.path(), url.scheme() == "https", now))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        if cookies.is_empty() {
+            None
+        } else {
+            Some(cookies.join("; "))
+        }
+    }
+
+    /// Keeps a `Set-Cookie` of a response to `url`, expired ones delete the cookie
+    pub fn store(&mut self, url: &surf::http::Url, set_cookie: &str) {
+        let parsed = match surf::http::Cookie::parse(set_cookie.to_string()) {
+            Ok(cookie) => cookie,
+            Err(_) => return,
+        };
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return,
+        };
+        let (domain, tailmatch) = match parsed.domain() {
+            Some(domain) => (domain.trim_start_matches('.').to_ascii_lowercase(), true),
+            None => (host.to_string(), false),
+        };
+        // cookies for other sites are ignored
+        if host != domain && !host.ends_with(&format!(".{}", domain)) {
+            return;
+        }
+        let path = match parsed.path() {
+            Some(path) if path.starts_with('/') => path.to_string(),
+            // the directory of the request path
+            _ => match url.path().rfind('/') {
+                Some(0) | None => "/".to_string(),
+                Some(i) => url.path()[..i].to_string(),
+            },
+        };
+        let expires = match (parsed.max_age(), parsed.expires()) {
+            (Some(max_age), _) => (now() as i64 + max_age.whole_seconds()).max(1) as u64,
+            (None, Some(at)) => at.unix_timestamp().max(1) as u64,
+            (None, None) => 0,
+        };
+        let cookie = Cookie {
+            domain,
+            tailmatch,
+            path,
+            secure: parsed.secure().unwrap_or(false),
+            http_only: parsed.http_only().unwrap_or(false),
+            expires,
+            name: parsed.name().to_string(),
+            value: parsed.value().to_string(),
+        };
+        self.cookies.retain(|c| {
+            (&c.name, &c.domain, &c.path) != (&cookie.name, &cookie.domain, &cookie.path)
+        });
+        if !cookie.expired(now()) {
+            self.cookies.push(cookie);
+        }
+    }
+}
+
+/// Surf middleware sending the cookies of the jar and keeping the ones responses set,
+/// redirects go through it hop by hop
+#[cfg(not(target_arch = "wasm32"))]
+pub struct CookieStore(pub Slice<CookieJar>);
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait::async_trait]
+impl surf::middleware::Middleware for CookieStore {
+    async fn handle(
+        &self,
+        mut req: surf::Request,
+        client: surf::Client,
+        next: surf::middleware::Next<'_>,
+    ) -> surf::Result<surf::Response> {
+        let url = req.url().clone();
+        if let Some(cookies) = self.0.get().header(&url) {
+            // cookies given with `-b name=value` come first
+            let cookies = match req.header("Cookie") {
+                Some(given) => format!("{}; {}", given.as_str(), cookies),
+                None => cookies,
+            };
+            req.insert_header("Cookie", cookies);
+        }
+        let res = next.run(req, client).await?;
+        if let Some(set_cookies) = res.header("Set-Cookie") {
+            let mut jar = self.0.write().await;
+            for set_cookie in set_cookies {
+                jar.store(&url, set_cookie.as_str());
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl std::fmt::Display for CookieJar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# Netscape HTTP Cookie File")?;
+        writeln!(f, "# https://curl.se/docs/http-cookies.html")?;
+        writeln!(
+            f,
+            "# This file was generated by qurl! Edit at your own risk.\n"
+        )?;
+        let flag = |b| if b { "TRUE" } else { "FALSE" };
+        for c in &self.cookies {
+            let mut line = String::new();
+            if c.http_only {
+                line.push_str("#HttpOnly_");
+            }
+            if c.tailmatch {
+                line.push('.');
+            }
+            write!(
+                line,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                c.domain,
+                flag(c.tailmatch),
+                c.path,
+                flag(c.secure),
+                c.expires,
+                c.name,
+                c.value
+            )?;
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie(domain: &str, tailmatch: bool, path: &str) -> Cookie {
+        Cookie {
+            domain: domain.to_string(),
+            tailmatch,
+            path: path.to_string(),
+            secure: false,
+            http_only: false,
+            expires: 0,
+            name: "n".to_string(),
+            value: "v".to_string(),
+        }
+    }
+
+    #[test]
+    fn netscape_round_trip() {
+        let text = "# Netscape HTTP Cookie File\n\
+                    .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n\
+                    #HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t2000000000\ttoken\tx=y\n\
+                    # a comment\n\
+                    malformed line\n";
+        let jar = CookieJar::parse(text);
+        assert_eq!(jar.cookies.len(), 2);
+        let session = &jar.cookies[0];
+        assert_eq!(
+            (session.domain.as_str(), session.tailmatch),
+            ("example.com", true)
+        );
+        assert!(!session.http_only);
+        let token = &jar.cookies[1];
+        assert_eq!(token.domain, "api.example.com");
+        assert!(token.http_only && token.secure && !token.tailmatch);
+        assert_eq!((token.path.as_str(), token.expires), ("/v1", 2000000000));
+        assert_eq!(token.value, "x=y");
+
+        let saved = jar.to_string();
+        assert!(saved.contains("\n.example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n"));
+        assert!(saved
+            .contains("\n#HttpOnly_api.example.com\tFALSE\t/v1\tTRUE\t2000000000\ttoken\tx=y\n"));
+        assert_eq!(CookieJar::parse(&saved), jar);
+    }
+
+    #[test]
+    fn domain_matching() {
+        let exact = cookie("example.com", false, "/");
+        assert!(exact.matches("example.com", "/", false, 0));
+        assert!(!exact.matches("www.example.com", "/", false, 0));
+        let tail = cookie("example.com", true, "/");
+        assert!(tail.matches("example.com", "/", false, 0));
+        assert!(tail.matches("www.example.com", "/", false, 0));
+        assert!(!tail.matches("badexample.com", "/", false, 0));
+    }
+
+    #[test]
+    fn path_matching() {
+        let c = cookie("h", false, "/docs");
+        assert!(c.matches("h", "/docs", false, 0));
+        assert!(c.matches("h", "/docs/a", false, 0));
+        assert!(!c.matches("h", "/docsearch", false, 0));
+        assert!(!c.matches("h", "/", false, 0));
+        let c = cookie("h", false, "/docs/");
+        assert!(c.matches("h", "/docs/a", false, 0));
+    }
+
+    #[test]
+    fn secure_and_expired() {
+        let mut c = cookie("h", false, "/");
+        c.secure = true;
+        assert!(!c.matches("h", "/", false, 0));
+        assert!(c.matches("h", "/", true, 0));
+        c.expires = 100;
+        assert!(c.matches("h", "/", true, 99));
+        assert!(!c.matches("h", "/", true, 100));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn set_cookie_scopes() {
+        let url = surf::http::Url::parse("http://www.example.com/a/b").unwrap();
+        let mut jar = CookieJar::default();
+        jar.store(&url, "host=1");
+        jar.store(&url, "site=2; Domain=.example.com; Path=/; HttpOnly");
+        jar.store(&url, "other=3; Domain=other.com");
+        assert_eq!(jar.cookies.len(), 2);
+        assert_eq!(
+            (jar.cookies[0].path.as_str(), jar.cookies[0].tailmatch),
+            ("/a", false)
+        );
+        assert!(jar.cookies[1].tailmatch && jar.cookies[1].http_only);
+        let sub = surf::http::Url::parse("http://api.example.com/").unwrap();
+        assert_eq!(jar.header(&sub).as_deref(), Some("site=2"));
+        assert_eq!(jar.header(&url).as_deref(), Some("host=1; site=2"));
+        jar.store(&url, "site=; Domain=example.com; Path=/; Max-Age=0");
+        assert_eq!(jar.header(&sub), None);
+    }
+}
@@ -0,0 +1,285 @@
+use super::{Client, CookieStore, Hop, Loading, Phases, Request, Response, Timing};
+use crate::state::{App, Data, Pane};
+use anyhow::{anyhow, bail, Result};
+use async_std::{fs::File, prelude::*};
+use async_store::{Cancel, State};
+use futures::{
+    future::{select, Either},
+    pin_mut,
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+use surf::http::{auth::BasicAuth, Method, Url};
+
+/// Sends `request` and streams the response body into [`App::inp_data`],
+/// a batch of lines per frame. Stops early once `cancel` is cancelled.
+pub async fn fetch(state: &State<App>, request: Request, cancel: &Cancel) -> Result<()> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let loading = Loading {
+        id,
+        method: request.method.clone(),
+        url: request.url.clone(),
+        ..Default::default()
+    };
+    let mut app = state.write().await;
+    app.loading = Some(loading);
+    // the message of the previous request is stale now
+    app.status = None;
+    app.dirty.insert(Pane::Status);
+    drop(app);
+    let res = stream(state, &request, cancel).await;
+    let current = |app: &App| app.loading.as_ref().map(|l| l.id) == Some(id);
+    // a newer request owns the indicator, leave the state alone
+    if current(&*state.read().await) {
+        let mut app = state.write().await;
+        if current(&app) {
+            app.loading = None;
+            app.dirty.insert(Pane::Status);
+        }
+    }
+    res
+}
+
+async fn stream(state: &State<App>, request: &Request, cancel: &Cancel) -> Result<()> {
+    let started = Instant::now();
+    let mut res = follow(state, request, cancel, started).await?;
+    if res.status().is_client_error() || res.status().is_server_error() {
+        let mut app = state.write().await;
+        app.status = Some(format!(
+            "{} {}",
+            res.status(),
+            res.status().canonical_reason()
+        ));
+        app.dirty.insert(Pane::Status);
+    }
+
+    let frame_rate = state.read().await.options.frame_rate;
+    let total = res.len().map(|len| len as u64);
+    let mut output = match &request.output {
+        Some(path) => Some(File::create(path).await?),
+        None => None,
+    };
+    let inp = state.slice(|s| &s.inp_data).await;
+    inp.set(Data::default()).await;
+    let mut body = res.take_body().into_reader();
+    let mut chunk = vec![0; 64 * 1024];
+    let mut received = 0;
+    let mut lines = vec![];
+    // bytes after the last line break, shown as the last line until it ends
+    let (mut partial, mut partial_shown) = (vec![], false);
+    let mut flushed_at = Instant::now();
+    loop {
+        let n = cancellable(cancel, body.read(&mut chunk)).await??;
+        if let Some(output) = &mut output {
+            output.write_all(&chunk[..n]).await?;
+        }
+        received += n as u64;
+        partial.extend_from_slice(&chunk[..n]);
+        if let Some(end) = partial.iter().rposition(|b| *b == b'\n') {
+            let rest = partial.split_off(end + 1);
+            lines.extend(partial[..end].split(|b| *b == b'\n').map(line));
+            partial = rest;
+        }
+        if n == 0 || flushed_at.elapsed() >= frame_rate {
+            // the request replacing this one may have started writing
+            if cancel.is_cancelled() {
+                bail!("request cancelled");
+            }
+            let mut data = inp.write().await;
+            if partial_shown {
+                data.original_lines.pop();
+            }
+            data.original_lines.append(&mut lines);
+            partial_shown = !partial.is_empty();
+            if partial_shown {
+                data.original_lines.push(line(&partial));
+            }
+            drop(data);
+            let mut app = state.write().await;
+            if let Some(loading) = &mut app.loading {
+                loading.received = received;
+                loading.total = total;
+                loading.ticks += 1;
+            }
+            app.response.size = received;
+            app.response.timing.total = started.elapsed();
+            app.dirty = app.dirty | Pane::Status | Pane::Response;
+            drop(app);
+            flushed_at = Instant::now();
+        }
+        if n == 0 {
+            break;
+        }
+    }
+    if let Some(output) = &mut output {
+        output.flush().await?;
+    }
+
+    if cancel.is_cancelled() {
+        bail!("request cancelled");
+    }
+    let text = inp.get().original_lines.join("\n");
+    let values = serde_json::Deserializer::from_str(&text)
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_default();
+    let mut data = inp.write().await;
+    data.original_loaded = true;
+    data.values = values;
+    data.values_loaded = true;
+    Ok(())
+}
+
+/// Text of a body line without its line break
+fn line(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    text.trim_end_matches('\r').to_string()
+}
+
+/// Sends `request`, following redirects the way curl's `-L` does when asked to.
+/// Every response on the way is recorded in [`App::response`] as it arrives.
+async fn follow(
+    state: &State<App>,
+    request: &Request,
+    cancel: &Cancel,
+    started: Instant,
+) -> Result<surf::Response> {
+    let client =
+        surf::Client::with_http_client(Client).with(CookieStore(state.slice(|s| &s.cookies).await));
+    let first = Url::parse(&request.url)?;
+    let mut method = request.method.clone();
+    let mut url = first.clone();
+    let mut body = request.body.as_deref();
+    let mut app = state.write().await;
+    app.response = Response::default();
+    app.dirty.insert(Pane::Response);
+    drop(app);
+    let mut followed = 0;
+    loop {
+        // credentials and cookies only go to the host they were given for unless trusted
+        let trusted = request.redirects.trusted || same_origin(&url, &first);
+        let req = build(&method, url.clone(), request, body, trusted)?;
+        let sent_at = Instant::now();
+        let res = cancellable(cancel, client.send(req))
+            .await?
+            .map_err(|e| e.into_inner())?;
+        let status = u16::from(res.status());
+        let location = match res.header("Location") {
+            Some(location) if request.redirects.follow && res.status().is_redirection() => {
+                Some(url.join(location.as_str())?)
+            }
+            _ => None,
+        };
+        let hop = Hop {
+            method: method.clone(),
+            url: url.to_string(),
+            status,
+            location: location.as_ref().map(Url::to_string),
+            took: sent_at.elapsed(),
+        };
+        let phases = res.ext::<Phases>().copied().unwrap_or_default();
+        let redirect = sent_at - started;
+        let mut app = state.write().await;
+        let response = &mut app.response;
+        response.hops.push(hop);
+        response.version = res.version().map(|v| v.to_string());
+        response.status = status;
+        response.reason = res.status().canonical_reason().to_string();
+        response.headers = (res.iter())
+            .flat_map(|(name, values)| {
+                values
+                    .iter()
+                    .map(move |v| (name.to_string(), v.to_string()))
+            })
+            .collect();
+        response.headers.sort_by(|a, b| a.0.cmp(&b.0));
+        response.content_type = res.header("Content-Type").map(|v| v.last().to_string());
+        response.timing = Timing {
+            redirect,
+            dns: redirect + phases.dns,
+            connect: redirect + phases.connect,
+            tls: phases.tls.map(|tls| redirect + tls),
+            first_byte: redirect + phases.first_byte,
+            total: started.elapsed(),
+        };
+        app.dirty.insert(Pane::Response);
+        drop(app);
+
+        let location = match location {
+            Some(location) => location,
+            None => return Ok(res),
+        };
+        if matches!(request.redirects.max, Some(max) if followed >= max) {
+            bail!("Maximum ({}) redirects followed", followed);
+        }
+        followed += 1;
+        let rewrite = match status {
+            301 | 302 => method == "POST" && !request.redirects.keep_post.contains(&status),
+            303 => method != "HEAD" && !request.redirects.keep_post.contains(&status),
+            _ => false,
+        };
+        if rewrite {
+            method = "GET".to_string();
+            body = None;
+        }
+        url = location;
+        let mut app = state.write().await;
+        if let Some(loading) = &mut app.loading {
+            loading.method = method.clone();
+            loading.url = url.to_string();
+        }
+        app.dirty.insert(Pane::Status);
+    }
+}
+
+/// One hop of `request`, with `body` unless a redirect dropped it
+fn build(
+    method: &str,
+    url: Url,
+    request: &Request,
+    body: Option<&[u8]>,
+    credentials: bool,
+) -> Result<surf::Request> {
+    let parsed: Method = method
+        .parse()
+        .map_err(|_| anyhow!("unknown method {}", method))?;
+    let mut req = surf::Request::new(parsed, url);
+    for (name, value) in &request.headers {
+        let secret = ["Authorization", "Proxy-Authorization", "Cookie"]
+            .iter()
+            .any(|secret| name.eq_ignore_ascii_case(secret));
+        // a body turned into a GET leaves its content type behind
+        let content_type =
+            name.eq_ignore_ascii_case("Content-Type") && body.is_none() && request.body.is_some();
+        if (credentials || !secret) && !content_type {
+            req.append_header(name.as_str(), value.as_str());
+        }
+    }
+    if let Some(user) = request.user.as_ref().filter(|_| credentials) {
+        let (name, password) = user.split_once(':').unwrap_or((user, ""));
+        let auth = BasicAuth::new(name, password);
+        req.insert_header(auth.name(), auth.value());
+    }
+    // after the headers, the body only sets a content type if they had none
+    if let Some(body) = body {
+        req.set_body(body.to_vec());
+    }
+    Ok(req)
+}
+
+fn same_origin(a: &Url, b: &Url) -> bool {
+    a.host_str() == b.host_str() && a.port_or_known_default() == b.port_or_known_default()
+}
+
+/// Runs `f` unless `cancel` fires first
+async fn cancellable<F: Future>(cancel: &Cancel, f: F) -> Result<F::Output> {
+    let cancelled = cancel.cancelled();
+    pin_mut!(f, cancelled);
+    match select(f, cancelled).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => bail!("request cancelled"),
+    }
+}
@@ -0,0 +1,141 @@
+mod body;
+#[cfg(not(target_arch = "wasm32"))]
+mod client;
+mod cookies;
+#[cfg(not(target_arch = "wasm32"))]
+mod fetch;
+mod response;
+pub use body::{Data, FormField};
+#[cfg(not(target_arch = "wasm32"))]
+pub use client::{Client, Phases};
+#[cfg(not(target_arch = "wasm32"))]
+pub use cookies::CookieStore;
+pub use cookies::{Cookie, CookieJar};
+#[cfg(not(target_arch = "wasm32"))]
+pub use fetch::fetch;
+pub use response::{Hop, Response, Timing};
+use serde::{Deserialize, Serialize};
+use std::{io, path::PathBuf};
+
+/// HTTP request as given on the command line, run by [`AppAction::Fetch`](crate::actions::AppAction::Fetch)
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    /// `(name, value)` pairs in the order given, repeated names are all sent
+    pub headers: Vec<(String, String)>,
+    /// `user:password` for basic auth
+    pub user: Option<String>,
+    pub body: Option<Vec<u8>>,
+    /// File the body is saved to as well
+    pub output: Option<PathBuf>,
+    pub redirects: Redirects,
+}
+
+/// How redirects are followed, curl's `-L` and the options around it
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Redirects {
+    pub follow: bool,
+    /// Hop limit, `None` for no limit
+    pub max: Option<u32>,
+    /// Send credentials to the other hosts redirected to as well
+    pub trusted: bool,
+    /// Statuses among 301, 302 and 303 keeping the method of a POST,
+    /// the others turn it into a GET without a body
+    pub keep_post: Vec<u16>,
+}
+impl Default for Redirects {
+    fn default() -> Self {
+        Redirects {
+            follow: false,
+            max: Some(50),
+            trusted: false,
+            keep_post: vec![],
+        }
+    }
+}
+
+impl Default for Request {
+    fn default() -> Self {
+        Request {
+            method: "GET".to_string(),
+            url: String::new(),
+            headers: vec![],
+            user: None,
+            body: None,
+            output: None,
+            redirects: Redirects::default(),
+        }
+    }
+}
+impl Request {
+    /// Parses a curl style `-H "Name: value"` header
+    pub fn parse_header(header: &str) -> Option<(String, String)> {
+        let (name, value) = header.split_once(':')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        Some((name.to_string(), value.trim().to_string()))
+    }
+    /// Posts the `-d` like options joined with `&`, or with `get` appends them
+    /// to the query string like curl's `-G`
+    pub fn data(mut self, data: &[Data], get: bool) -> io::Result<Self> {
+        if data.is_empty() {
+            return Ok(self);
+        }
+        let data = body::data(data)?;
+        if get {
+            self.url = body::with_query(&self.url, &data);
+            self.method = "GET".to_string();
+        } else {
+            self.method = "POST".to_string();
+            self.body = Some(data);
+            self.content_type(body::FORM_URLENCODED);
+        }
+        Ok(self)
+    }
+    /// Posts the `-F` fields as `multipart/form-data`
+    pub fn form(mut self, fields: &[FormField]) -> Self {
+        if fields.is_empty() {
+            return self;
+        }
+        let (content_type, form) = body::multipart(fields);
+        self.method = "POST".to_string();
+        self.body = Some(form);
+        self.content_type(&content_type);
+        self
+    }
+    /// Sets `Content-Type` unless a header gave one
+    fn content_type(&mut self, content_type: &str) {
+        if !self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        {
+            self.headers
+                .push(("Content-Type".to_string(), content_type.to_string()));
+        }
+    }
+}
+
+/// Progress of the running request, shown in the status bar
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
+pub struct Loading {
+    /// Tells a cancelled request from the one replacing it
+    pub id: u64,
+    pub method: String,
+    pub url: String,
+    /// Body bytes received so far
+    pub received: u64,
+    /// `Content-Length` if the server sent one
+    pub total: Option<u64>,
+    /// Bumped on every update, animates the spinner
+    pub ticks: usize,
+}
+impl Loading {
+    const SPINNER: [char; 8] = ['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
+    pub fn spinner(&self) -> char {
+        Loading::SPINNER[self.ticks % Loading::SPINNER.len()]
+    }
+}
@@ -0,0 +1,209 @@
+//! What came back for a request: the redirect chain, the metadata of the last
+//! response and its timing, shown in the response panel and printed by `-w`.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// One response of a request, `location` is set for the redirects that were followed
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct Hop {
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    pub location: Option<String>,
+    /// From sending the request to the response headers
+    pub took: Duration,
+}
+
+/// Times since the request started, redirects included, like curl's `time_*` variables
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
+pub struct Timing {
+    /// Spent on the redirects before the last hop
+    pub redirect: Duration,
+    pub dns: Duration,
+    pub connect: Duration,
+    /// `None` over plain HTTP
+    pub tls: Option<Duration>,
+    pub first_byte: Duration,
+    /// Once the whole body was read
+    pub total: Duration,
+}
+
+/// Last request, shown in the response panel
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
+pub struct Response {
+    /// Every response on the way, redirects first
+    pub hops: Vec<Hop>,
+    /// `HTTP/1.1`, of the last response like the fields below
+    pub version: Option<String>,
+    pub status: u16,
+    pub reason: String,
+    /// `(name, value)` pairs sorted by their lowercase names
+    pub headers: Vec<(String, String)>,
+    pub content_type: Option<String>,
+    /// Body bytes received so far
+    pub size: u64,
+    pub timing: Timing,
+}
+
+impl Response {
+    /// `HTTP/1.1 200 OK`
+    pub fn status_line(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.version.as_deref().unwrap_or("HTTP"),
+            self.status,
+            self.reason
+        )
+    }
+
+    /// Values of the header `name`, joined like repeated headers are
+    pub fn header(&self, name: &str) -> Option<String> {
+        let values: Vec<_> = (self.headers.iter())
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    /// Fills a curl `--write-out` template: `%{variable}`, `%header{name}`, `%%`
+    /// and the `\n`, `\r`, `\t` escapes. Unknown variables print nothing like in curl
+    pub fn write_out(&self, template: &str) -> String {
+        let mut out = String::new();
+        let mut rest = template;
+        while let Some(c) = rest.chars().next() {
+            let (text, len) = if let Some(var) = rest.strip_prefix("%{") {
+                match var.split_once('}') {
+                    Some((name, _)) => (self.variable(name), name.len() + 3),
+                    None => (c.to_string(), 1),
+                }
+            } else if let Some(var) = rest.strip_prefix("%header{") {
+                match var.split_once('}') {
+                    Some((name, _)) => (self.header(name).unwrap_or_default(), name.len() + 9),
+                    None => (c.to_string(), 1),
+                }
+            } else if rest.starts_with("%%") {
+                ("%".to_string(), 2)
+            } else if c == '\\' {
+                match rest[1..].chars().next() {
+                    Some('n') => ("\n".to_string(), 2),
+                    Some('r') => ("\r".to_string(), 2),
+                    Some('t') => ("\t".to_string(), 2),
+                    Some('\\') => ("\\".to_string(), 2),
+                    _ => (c.to_string(), 1),
+                }
+            } else {
+                (c.to_string(), c.len_utf8())
+            };
+            out.push_str(&text);
+            rest = &rest[len..];
+        }
+        out
+    }
+
+    fn variable(&self, name: &str) -> String {
+        let secs = |d: Duration| format!("{:.6}", d.as_secs_f64());
+        let last = self.hops.last();
+        match name {
+            "http_code" | "response_code" => format!("{:03}", self.status),
+            "http_version" => (self.version.as_deref())
+                .map(|v| v.trim_start_matches("HTTP/").to_string())
+                .unwrap_or_else(|| "0".to_string()),
+            "method" => last.map(|h| h.method.clone()).unwrap_or_default(),
+            "url_effective" => last.map(|h| h.url.clone()).unwrap_or_default(),
+            "num_redirects" => self.hops.len().saturating_sub(1).to_string(),
+            "num_headers" => self.headers.len().to_string(),
+            "content_type" => self.content_type.clone().unwrap_or_default(),
+            "size_download" => self.size.to_string(),
+            "time_redirect" => secs(self.timing.redirect),
+            "time_namelookup" => secs(self.timing.dns),
+            "time_connect" => secs(self.timing.connect),
+            "time_appconnect" => secs(self.timing.tls.unwrap_or_default()),
+            "time_starttransfer" => secs(self.timing.first_byte),
+            "time_total" => secs(self.timing.total),
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> Response {
+        Response {
+            hops: vec![
+                Hop {
+                    method: "GET".to_string(),
+                    url: "http://a/".to_string(),
+                    status: 302,
+                    location: Some("http://b/".to_string()),
+                    took: Duration::from_millis(5),
+                },
+                Hop {
+                    method: "GET".to_string(),
+                    url: "http://b/".to_string(),
+                    status: 200,
+                    location: None,
+                    took: Duration::from_millis(7),
+                },
+            ],
+            version: Some("HTTP/1.1".to_string()),
+            status: 200,
+            reason: "OK".to_string(),
+            headers: vec![
+                ("content-type".to_string(), "application/json".to_string()),
+                ("set-cookie".to_string(), "a=1".to_string()),
+                ("Set-Cookie".to_string(), "b=2".to_string()),
+            ],
+            content_type: Some("application/json".to_string()),
+            size: 42,
+            timing: Timing {
+                total: Duration::from_millis(1500),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn variables() {
+        let out = response().write_out(
+            "%{http_code} %{http_version} %{method} %{url_effective} %{num_redirects} \
+             %{size_download} %{content_type} %{time_total} %{time_appconnect}",
+        );
+        assert_eq!(
+            out,
+            "200 1.1 GET http://b/ 1 42 application/json 1.500000 0.000000"
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let out = response().write_out("a\\nb\\tc\\r\\\\ 100%% \\x %");
+        assert_eq!(out, "a\nb\tc\r\\ 100% \\x %");
+    }
+
+    #[test]
+    fn headers() {
+        let res = response();
+        assert_eq!(res.write_out("%header{Content-Type}"), "application/json");
+        assert_eq!(res.write_out("[%header{set-cookie}]"), "[a=1, b=2]");
+        assert_eq!(res.write_out("[%header{missing}]"), "[]");
+        assert_eq!(res.write_out("%header{unclosed"), "%header{unclosed");
+    }
+
+    #[test]
+    fn unknown_variables_print_nothing() {
+        let res = response();
+        assert_eq!(res.write_out("[%{nope}]"), "[]");
+        assert_eq!(res.write_out("%{http_code"), "%{http_code");
+        assert_eq!(
+            Response::default().write_out("%{http_code} %{url_effective}"),
+            "000 "
+        );
+    }
+}
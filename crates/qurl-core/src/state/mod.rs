@@ -1,14 +1,24 @@
-use std::time::{Duration, Instant};
+use crate::{
+    actions::RectDef,
+    request::{CookieJar, Loading, Response},
+};
+use async_store::{Observable, Slice};
+use serde::{Serialize, Serializer};
+use std::{
+    iter::FromIterator,
+    ops::{BitAnd, BitOr},
+    time::{Duration, Instant},
+};
 
-use syntect::highlighting::{Style as SyntStyle, ThemeSet};
-use tui::backend::Backend;
-use tui::layout::Alignment;
+use syntect::highlighting::Style as SyntStyle;
+use tui::buffer::Buffer;
+use tui::layout::{Constraint, Direction, Layout};
 use tui::style::{Color, Style};
-use tui::terminal::Frame;
-use tui::widgets::{Block, Borders, Paragraph};
+use tui::text::{Span, Text};
+use tui::widgets::{Block, Borders, Paragraph, Widget, Wrap};
 use tui::{layout::Rect, text::Spans};
 
-#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize)]
 pub enum DataFmt {
     JSON,
     YAML,
@@ -21,11 +31,81 @@ impl Default for DataFmt {
         DataFmt::JSON
     }
 }
-#[derive(PartialEq, Debug, Default)]
+/// Regions of the screen, each one is redrawn only when marked in [`App::dirty`]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize)]
+pub enum Pane {
+    Input,
+    Query,
+    Output,
+    Schema,
+    Status,
+    Cookies,
+    Response,
+}
+impl Pane {
+    pub const ALL: [Pane; 7] = [
+        Pane::Input,
+        Pane::Query,
+        Pane::Output,
+        Pane::Schema,
+        Pane::Status,
+        Pane::Cookies,
+        Pane::Response,
+    ];
+}
+
+/// Set of [`Pane`]s
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug, Serialize)]
+pub struct Panes(u8);
+impl Panes {
+    pub const NONE: Panes = Panes(0);
+    pub const ALL: Panes = Panes(0b1111111);
+    pub fn insert(&mut self, pane: Pane) {
+        self.0 |= Panes::from(pane).0;
+    }
+    pub fn contains(&self, pane: Pane) -> bool {
+        self.0 & Panes::from(pane).0 != 0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    pub fn iter(self) -> impl Iterator<Item = Pane> {
+        Pane::ALL.iter().copied().filter(move |p| self.contains(*p))
+    }
+}
+impl From<Pane> for Panes {
+    fn from(pane: Pane) -> Self {
+        Panes(1 << pane as u8)
+    }
+}
+impl<P: Into<Panes>> BitOr<P> for Panes {
+    type Output = Panes;
+    fn bitor(self, other: P) -> Panes {
+        Panes(self.0 | other.into().0)
+    }
+}
+impl<P: Into<Panes>> BitAnd<P> for Panes {
+    type Output = Panes;
+    fn bitand(self, other: P) -> Panes {
+        Panes(self.0 & other.into().0)
+    }
+}
+impl FromIterator<Pane> for Panes {
+    fn from_iter<I: IntoIterator<Item = Pane>>(iter: I) -> Self {
+        iter.into_iter().fold(Panes::NONE, |panes, p| panes | p)
+    }
+}
+
+/// Serialized for the inspector with line and value counts only,
+/// the body would otherwise be in every state diff twice
+#[derive(PartialEq, Debug, Default, Clone, Serialize)]
 pub struct Data {
     pub format: DataFmt,
+    #[serde(serialize_with = "count")]
     pub original_lines: Vec<String>,
+    #[serde(skip)]
     pub formatted_lines: Vec<Vec<(SyntStyle, String)>>,
+    #[serde(serialize_with = "count")]
     pub values: Vec<serde_json::Value>,
     pub scroll: (u16, u16),
     pub original_loaded: bool,
@@ -33,28 +113,61 @@ pub struct Data {
     pub formatted_loaded: bool,
 }
 
-#[derive(PartialEq, Debug, Default)]
+fn count<T, S: Serializer>(items: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(items.len() as u64)
+}
+
+#[derive(PartialEq, Debug, Default, Clone, Serialize)]
 pub struct Options {
     pub tick_rate: Duration,
     pub frame_rate: Duration,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Serialize, Observable)]
 pub struct App {
     pub options: Options,
-    pub inp_data: Data,
-    pub out_data: Data,
-    pub schema_data: Data,
+    /// Big and written while loading, kept in slices so rendering doesn't wait
+    pub inp_data: Slice<Data>,
+    pub out_data: Slice<Data>,
+    pub schema_data: Slice<Data>,
 
+    #[serde(with = "RectDef")]
     pub size: Rect,
     pub input_cursor_position: u16,
 
     pub jq_input: String,
+    /// Last error message shown in the status bar
+    pub status: Option<String>,
+    /// Request in flight, the status bar shows its progress
+    pub loading: Option<Loading>,
+    /// Sent with and filled by every request of the session
+    pub cookies: Slice<CookieJar>,
+    /// Selected row of the cookies panel
+    pub cookies_selected: usize,
+    /// Redirects and metadata of the last request
+    pub response: Response,
+    /// Cookies or response panel open in place of the schema
+    pub panel: Option<Pane>,
     pub mouse_pos: (u16, u16),
+    #[serde(skip)]
     pub last_render_at: Instant,
-    pub need_render: bool,
+    /// Panes changed since the last frame, the render scheduler takes them.
+    /// Writes to the slices mark their panes on their own.
+    pub dirty: Panes,
     pub running: bool,
 }
+/// What undo and redo bring back, the edits made with the keyboard.
+/// The screen size, requests in flight and their response stay as they are.
+/// Slices are left out, requests write them through shared handles and
+/// snapshots would keep every body alive.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Edits {
+    pub jq_input: String,
+    pub input_cursor_position: u16,
+    pub panel: Option<Pane>,
+    pub cookies_selected: usize,
+}
+
 impl Default for App {
     fn default() -> Self {
         App {
@@ -62,48 +175,304 @@ impl Default for App {
                 tick_rate: Duration::from_millis(160),
                 frame_rate: Duration::from_millis(33),
             },
-            inp_data: Data::default(),
-            out_data: Data::default(),
-            schema_data: Data {
+            inp_data: Slice::default(),
+            out_data: Slice::default(),
+            schema_data: Slice::new(Data {
                 format: DataFmt::SCHEMA,
                 ..Default::default()
-            },
+            }),
 
             size: Rect::default(),
             input_cursor_position: 0,
 
             jq_input: ".".to_string(),
+            status: None,
+            loading: None,
+            cookies: Slice::default(),
+            cookies_selected: 0,
+            response: Response::default(),
+            panel: None,
             mouse_pos: (0, 0),
             last_render_at: Instant::now(),
-            need_render: false,
+            dirty: Panes::ALL,
             running: true,
         }
     }
 }
 impl App {
-    pub fn render<B: Backend>(&self, frame: &mut Frame<'_, B>) {
-        let size = frame.size();
-        // This is where you add new widgets.
-        // See the following resources:
-        // - https://docs.rs/tui/0.16.0/tui/widgets/index.html
-        // - https://github.com/fdehau/tui-rs/tree/v0.16.0/examples
-        frame.render_widget(
-            Paragraph::new(format!("{}ms", self.last_render_at.elapsed().as_millis()))
-                .block(Block::default().borders(Borders::ALL))
-                .style(Style::default().fg(Color::White))
-                .alignment(Alignment::Center),
-            frame.size(),
-        );
-        frame.render_widget(
-            Block::default()
-                .borders(Borders::NONE)
-                .style(Style::default().fg(Color::White).bg(Color::LightYellow)),
+    pub fn edits(&self) -> Edits {
+        Edits {
+            jq_input: self.jq_input.clone(),
+            input_cursor_position: self.input_cursor_position,
+            panel: self.panel,
+            cookies_selected: self.cookies_selected,
+        }
+    }
+    pub fn restore(&mut self, edits: Edits) {
+        self.jq_input = edits.jq_input;
+        self.input_cursor_position = edits.input_cursor_position;
+        self.panel = edits.panel;
+        self.cookies_selected = edits.cookies_selected;
+    }
+    /// Where the panes go on a screen of `size`, the schema only fits wide screens
+    /// and gives way to the open panel
+    pub fn layout(&self, size: Rect) -> Vec<(Pane, Rect)> {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(size);
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(if size.width >= 120 || self.panel.is_some() {
+                vec![Constraint::Ratio(1, 3); 3]
+            } else {
+                vec![
+                    Constraint::Ratio(1, 2),
+                    Constraint::Ratio(1, 2),
+                    Constraint::Length(0),
+                ]
+            })
+            .split(rows[1]);
+        let third = |pane| match self.panel {
+            Some(panel) if panel == pane => columns[2],
+            None if pane == Pane::Schema => columns[2],
+            _ => Rect::default(),
+        };
+        vec![
+            (Pane::Query, rows[0]),
+            (Pane::Input, columns[0]),
+            (Pane::Output, columns[1]),
+            (Pane::Schema, third(Pane::Schema)),
+            (Pane::Cookies, third(Pane::Cookies)),
+            (Pane::Response, third(Pane::Response)),
+            (Pane::Status, rows[2]),
+        ]
+    }
+    /// Panes taking up some room on the screen
+    pub fn visible(&self) -> Panes {
+        self.layout(self.size)
+            .into_iter()
+            .filter(|(_, area)| area.area() > 0)
+            .map(|(pane, _)| pane)
+            .collect()
+    }
+    pub fn pane_at(&self, (x, y): (u16, u16)) -> Option<Pane> {
+        self.layout(self.size)
+            .into_iter()
+            .find(|(_, a)| x >= a.x && x < a.right() && y >= a.y && y < a.bottom())
+            .map(|(pane, _)| pane)
+    }
+    pub fn render_pane(&self, pane: Pane, area: Rect, buf: &mut Buffer) {
+        let block = |title| Block::default().borders(Borders::ALL).title(title);
+        match pane {
+            Pane::Query => Paragraph::new(self.jq_input.as_str())
+                .block(block("jq"))
+                .render(area, buf),
+            Pane::Input => render_data(&self.inp_data.get(), block("input"), area, buf),
+            Pane::Output => render_data(&self.out_data.get(), block("output"), area, buf),
+            Pane::Schema => render_data(&self.schema_data.get(), block("schema"), area, buf),
+            Pane::Cookies => {
+                let selected = self.cookies_selected;
+                let lines: Vec<_> = self
+                    .cookies
+                    .get()
+                    .cookies
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let style = if i == selected {
+                            Style::default().bg(Color::DarkGray)
+                        } else {
+                            Style::default()
+                        };
+                        Spans::from(vec![
+                            Span::styled(format!("{}{}", c.domain, c.path), style.fg(Color::Cyan)),
+                            Span::styled(format!(" {}={}", c.name, c.value), style),
+                        ])
+                    })
+                    .collect();
+                let scroll = selected.saturating_sub(area.height.saturating_sub(3) as usize);
+                Paragraph::new(lines)
+                    .block(block("cookies, Del removes"))
+                    .scroll((scroll as u16, 0))
+                    .render(area, buf)
+            }
+            Pane::Response => {
+                let response = &self.response;
+                let gray = Style::default().fg(Color::DarkGray);
+                let status = |status| {
+                    let color = match status {
+                        200..=299 => Color::Green,
+                        300..=399 => Color::Yellow,
+                        _ => Color::Red,
+                    };
+                    Style::default().fg(color)
+                };
+                let mut lines = vec![];
+                if response.hops.is_empty() {
+                    lines.push(Spans::from(Span::styled("no response yet", gray)));
+                } else {
+                    let timing = &response.timing;
+                    let ms = |d: Duration| format!("{}ms", d.as_millis());
+                    lines.push(Spans::from(Span::styled(
+                        response.status_line(),
+                        status(response.status),
+                    )));
+                    lines.push(Spans::from(format!(
+                        "{}  {}",
+                        response
+                            .content_type
+                            .as_deref()
+                            .unwrap_or("no content type"),
+                        bytes(response.size)
+                    )));
+                    lines.push(Spans::from(Span::styled(
+                        format!(
+                            "dns {}  connect {}  tls {}  first byte {}  total {}",
+                            ms(timing.dns),
+                            ms(timing.connect),
+                            timing.tls.map_or("-".to_string(), ms),
+                            ms(timing.first_byte),
+                            ms(timing.total)
+                        ),
+                        gray,
+                    )));
+                }
+                if response.hops.len() > 1 {
+                    lines.push(Spans::default());
+                    for (i, hop) in response.hops.iter().enumerate() {
+                        let mut spans = vec![
+                            Span::raw(format!("{}. {} {} ", i + 1, hop.method, hop.url)),
+                            Span::styled(hop.status.to_string(), status(hop.status)),
+                        ];
+                        if let Some(location) = &hop.location {
+                            spans.push(Span::styled(
+                                format!(" → {}", location),
+                                Style::default().fg(Color::Cyan),
+                            ));
+                        }
+                        spans.push(Span::styled(format!("  {}ms", hop.took.as_millis()), gray));
+                        lines.push(Spans::from(spans));
+                    }
+                }
+                if !response.headers.is_empty() {
+                    lines.push(Spans::default());
+                }
+                for (name, value) in &response.headers {
+                    lines.push(Spans::from(vec![
+                        Span::styled(format!("{}: ", name), Style::default().fg(Color::Cyan)),
+                        Span::raw(value.as_str()),
+                    ]));
+                }
+                Paragraph::new(lines)
+                    .block(block("response"))
+                    .wrap(Wrap { trim: false })
+                    .render(area, buf)
+            }
+            Pane::Status => match (&self.loading, &self.status) {
+                (Some(loading), _) => Paragraph::new(format!(
+                    "{} {} {} {}{}  Esc cancels",
+                    loading.spinner(),
+                    loading.method,
+                    loading.url,
+                    bytes(loading.received),
+                    loading
+                        .total
+                        .map(|t| format!("/{}", bytes(t)))
+                        .unwrap_or_default()
+                ))
+                .style(Style::default().fg(Color::Yellow))
+                .render(area, buf),
+                (None, Some(status)) => Paragraph::new(status.as_str())
+                    .style(Style::default().fg(Color::Red))
+                    .render(area, buf),
+                (None, None) => Paragraph::new("^Q quit  ^Z undo  ^Y redo")
+                    .style(Style::default().fg(Color::DarkGray))
+                    .render(area, buf),
+            },
+        }
+    }
+    /// Drawn over the panes on every frame
+    pub fn render_cursor(&self, buf: &mut Buffer) {
+        let area = buf.area;
+        if area.area() == 0 {
+            return;
+        }
+        buf.set_style(
             Rect {
-                x: self.mouse_pos.0.min(size.width - 1),
-                y: self.mouse_pos.1.min(size.height - 1),
-                width: 1u16,
-                height: 1u16,
+                x: self.mouse_pos.0.min(area.right() - 1),
+                y: self.mouse_pos.1.min(area.bottom() - 1),
+                width: 1,
+                height: 1,
             },
+            Style::default().fg(Color::White).bg(Color::LightYellow),
         );
     }
 }
+
+/// Byte count the way humans read it, `12.3 KB`
+fn bytes(n: u64) -> String {
+    match n {
+        0..=1023 => format!("{} B", n),
+        1024..=1_048_575 => format!("{:.1} KB", n as f64 / 1024.0),
+        _ => format!("{:.1} MB", n as f64 / 1_048_576.0),
+    }
+}
+
+/// Highlighted lines once formatted, the original ones until then
+fn render_data(data: &Data, block: Block, area: Rect, buf: &mut Buffer) {
+    let text: Text = if data.formatted_loaded {
+        data.formatted_lines
+            .iter()
+            .map(|line| {
+                Spans::from(
+                    line.iter()
+                        .map(|(style, s)| {
+                            let fg = style.foreground;
+                            Span::styled(
+                                s.as_str(),
+                                Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b)),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .into()
+    } else {
+        data.original_lines
+            .iter()
+            .map(|line| Spans::from(line.as_str()))
+            .collect::<Vec<_>>()
+            .into()
+    };
+    Paragraph::new(text)
+        .block(block)
+        .scroll(data.scroll)
+        .render(area, buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn data_serializes_counts_instead_of_the_body() {
+        let data = Data {
+            original_lines: vec!["{\"a\": 1}".into(), "{\"a\": 2}".into()],
+            values: vec![json!({"a": 1}), json!({"a": 2})],
+            original_loaded: true,
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(value["original_lines"], 2);
+        assert_eq!(value["values"], 2);
+        assert_eq!(value["original_loaded"], true);
+        assert_eq!(value.get("formatted_lines"), None);
+    }
+}
@@ -0,0 +1,321 @@
+use async_std::{
+    net::{TcpListener, TcpStream},
+    prelude::*,
+    task,
+};
+use async_store::{Cancel, State};
+use qurl_core::{
+    request::{fetch, Redirects, Request},
+    state::App,
+};
+use serde_json::json;
+use std::{fs, time::Duration};
+
+/// Answers one request with a response of `status` whose body is written in
+/// `parts`, each after its delay. Returns the URL to send the request to.
+async fn serve(status: &str, parts: Vec<(u64, &'static str)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let len: usize = parts.iter().map(|(_, part)| part.len()).sum();
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        status, len
+    );
+    task::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        read_head(&mut stream).await;
+        stream.write_all(head.as_bytes()).await.unwrap();
+        for (delay, part) in parts {
+            task::sleep(Duration::from_millis(delay)).await;
+            if stream.write_all(part.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    });
+    url
+}
+
+async fn read_head(stream: &mut TcpStream) -> String {
+    let mut head = vec![];
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).await.unwrap();
+        head.push(byte[0]);
+    }
+    String::from_utf8(head).unwrap()
+}
+
+/// Answers a request per response on a port of its own, one connection each.
+/// Returns the base URL and the requests received, head and body.
+async fn hops(responses: Vec<String>) -> (String, task::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = task::spawn(async move {
+        let mut requests = vec![];
+        for response in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let head = read_head(&mut stream).await;
+            let len = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+                .map_or(0, |(_, len)| len.trim().parse().unwrap());
+            let mut body = vec![0; len];
+            stream.read_exact(&mut body).await.unwrap();
+            stream.write_all(response.as_bytes()).await.unwrap();
+            requests.push(head + &String::from_utf8(body).unwrap());
+        }
+        requests
+    });
+    (url, requests)
+}
+
+fn redirect(status: &str, location: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status, location
+    )
+}
+
+fn ok(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+/// POST of `a=1` following redirects
+fn post(url: String) -> Request {
+    Request {
+        method: "POST".to_string(),
+        body: Some(b"a=1".to_vec()),
+        redirects: Redirects {
+            follow: true,
+            ..Default::default()
+        },
+        ..get(url)
+    }
+}
+
+/// Flushes every chunk as it arrives
+fn state() -> State<App> {
+    let mut app = App::default();
+    app.options.frame_rate = Duration::from_millis(0);
+    State::new(app)
+}
+
+fn get(url: String) -> Request {
+    Request {
+        url,
+        ..Default::default()
+    }
+}
+
+/// Polls `check` for a second
+async fn eventually(state: &State<App>, check: impl Fn(&App) -> bool) {
+    for _ in 0..100 {
+        if check(&*state.read().await) {
+            return;
+        }
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{:?}", state.read().await.inp_data);
+}
+
+#[test]
+fn streams_a_single_line_body_as_it_arrives() {
+    task::block_on(async {
+        let url = serve("200 OK", vec![(0, "[1,2,"), (300, "3,4]\n")]).await;
+        let state = state();
+        let fetching = {
+            let state = state.clone();
+            task::spawn(async move { fetch(&state, get(url), &Cancel::default()).await })
+        };
+        // the unfinished line shows up before the rest arrives
+        eventually(&state, |app| {
+            app.inp_data.get().original_lines == ["[1,2,"]
+                && app.loading.as_ref().map(|l| l.received) == Some(5)
+        })
+        .await;
+        fetching.await.unwrap();
+        let app = state.read().await;
+        let data = app.inp_data.get();
+        assert_eq!(data.original_lines, ["[1,2,3,4]"]);
+        assert_eq!(data.values, [json!([1, 2, 3, 4])]);
+        assert!(data.values_loaded);
+        assert_eq!(app.response.size, 10);
+        assert_eq!(app.loading, None);
+    });
+}
+
+#[test]
+fn client_errors_go_to_the_status_bar() {
+    task::block_on(async {
+        let url = serve("404 Not Found", vec![(0, "{\"error\": \"missing\"}\n")]).await;
+        let state = state();
+        fetch(&state, get(url), &Cancel::default()).await.unwrap();
+        let app = state.read().await;
+        assert_eq!(app.status.as_deref(), Some("404 Not Found"));
+        assert_eq!(app.response.status, 404);
+        assert_eq!(app.inp_data.get().values, [json!({"error": "missing"})]);
+        drop(app);
+        // the next request starts with a clear status bar
+        let url = serve("200 OK", vec![(0, "{}\n")]).await;
+        fetch(&state, get(url), &Cancel::default()).await.unwrap();
+        assert_eq!(state.read().await.status, None);
+    });
+}
+
+#[test]
+fn output_gets_the_exact_body() {
+    let path = std::env::temp_dir().join(format!("qurl-output-{}", std::process::id()));
+    task::block_on(async {
+        let url = serve("200 OK", vec![(0, "{\"a\": 1}\r\n"), (50, "{\"a\": 2}")]).await;
+        let state = state();
+        let request = Request {
+            output: Some(path.clone()),
+            ..get(url)
+        };
+        fetch(&state, request, &Cancel::default()).await.unwrap();
+        let data = state.read().await.inp_data.get();
+        assert_eq!(data.original_lines, ["{\"a\": 1}", "{\"a\": 2}"]);
+        assert_eq!(data.values, [json!({"a": 1}), json!({"a": 2})]);
+    });
+    assert_eq!(fs::read(&path).unwrap(), b"{\"a\": 1}\r\n{\"a\": 2}");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn cancel_stops_a_stalled_body() {
+    task::block_on(async {
+        let url = serve("200 OK", vec![(0, "[1,"), (10_000, "2]")]).await;
+        let state = state();
+        let cancel = Cancel::default();
+        let fetching = {
+            let (state, cancel) = (state.clone(), cancel.clone());
+            task::spawn(async move { fetch(&state, get(url), &cancel).await })
+        };
+        eventually(&state, |app| app.inp_data.get().original_lines == ["[1,"]).await;
+        cancel.cancel();
+        let res = async_std::future::timeout(Duration::from_secs(1), fetching).await;
+        let error = res.expect("fetch didn't stop").unwrap_err();
+        assert_eq!(error.to_string(), "request cancelled");
+        let app = state.read().await;
+        assert_eq!(app.loading, None);
+        assert!(!app.inp_data.get().values_loaded);
+    });
+}
+
+#[test]
+fn follows_relative_redirects_with_location_only() {
+    task::block_on(async {
+        let (url, requests) = hops(vec![redirect("302 Found", "next"), ok("{}\n")]).await;
+        let state = state();
+        let request = Request {
+            redirects: Redirects {
+                follow: true,
+                ..Default::default()
+            },
+            ..get(format!("{}/dir/start", url))
+        };
+        fetch(&state, request, &Cancel::default()).await.unwrap();
+        let requests = requests.await;
+        assert!(requests[1].starts_with("GET /dir/next "), "{}", requests[1]);
+        let app = state.read().await;
+        assert_eq!(app.response.hops.len(), 2);
+        assert_eq!(app.response.status, 200);
+        drop(app);
+
+        // without -L the redirect is the response
+        let (url, requests) = hops(vec![redirect("302 Found", "next")]).await;
+        fetch(&state, get(url), &Cancel::default()).await.unwrap();
+        assert_eq!(requests.await.len(), 1);
+        assert_eq!(state.read().await.response.status, 302);
+    });
+}
+
+#[test]
+fn max_redirs_stops_following() {
+    task::block_on(async {
+        let (url, requests) = hops(vec![
+            redirect("302 Found", "/again"),
+            redirect("302 Found", "/again"),
+        ])
+        .await;
+        let mut request = post(url);
+        request.redirects.max = Some(1);
+        let error = fetch(&state(), request, &Cancel::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Maximum (1) redirects followed");
+        assert_eq!(requests.await.len(), 2);
+    });
+}
+
+#[test]
+fn redirects_turn_a_post_into_a_get_unless_kept() {
+    task::block_on(async {
+        for (status, keep) in [
+            ("301 Moved Permanently", false),
+            ("302 Found", false),
+            ("303 See Other", false),
+            ("301 Moved Permanently", true),
+            ("302 Found", true),
+            ("303 See Other", true),
+            ("307 Temporary Redirect", false),
+            ("308 Permanent Redirect", false),
+        ] {
+            let (url, requests) = hops(vec![redirect(status, "/next"), ok("{}\n")]).await;
+            let mut request = post(url);
+            if keep {
+                // --post301, --post302 and --post303
+                request.redirects.keep_post = vec![status[..3].parse().unwrap()];
+            }
+            fetch(&state(), request, &Cancel::default()).await.unwrap();
+            let next = &requests.await[1];
+            let kept = keep || status.starts_with("307") || status.starts_with("308");
+            if kept {
+                assert!(next.starts_with("POST /next "), "{}: {}", status, next);
+                assert!(next.ends_with("\r\n\r\na=1"), "{}: {}", status, next);
+            } else {
+                assert!(next.starts_with("GET /next "), "{}: {}", status, next);
+                assert!(next.ends_with("\r\n\r\n"), "{}: {}", status, next);
+            }
+        }
+    });
+}
+
+#[test]
+fn credentials_stay_with_their_host_unless_trusted() {
+    task::block_on(async {
+        for trusted in [false, true] {
+            let (other, received) = hops(vec![ok("{}\n")]).await;
+            let (url, _) = hops(vec![redirect("302 Found", &format!("{}/", other))]).await;
+            let request = Request {
+                user: Some("user:secret".to_string()),
+                headers: vec![
+                    ("Authorization".to_string(), "Bearer token".to_string()),
+                    (
+                        "Proxy-Authorization".to_string(),
+                        "Basic cHJveHk=".to_string(),
+                    ),
+                    ("Cookie".to_string(), "session=1".to_string()),
+                    ("Accept".to_string(), "application/json".to_string()),
+                ],
+                redirects: Redirects {
+                    follow: true,
+                    trusted,
+                    ..Default::default()
+                },
+                ..get(url)
+            };
+            fetch(&state(), request, &Cancel::default()).await.unwrap();
+            let head = received.await[0].to_lowercase();
+            assert!(head.contains("accept: application/json"), "{}", head);
+            for secret in ["authorization:", "proxy-authorization:", "cookie:"] {
+                assert_eq!(head.contains(secret), trusted, "{}: {}", secret, head);
+            }
+        }
+    });
+}
@@ -0,0 +1,89 @@
+use async_std::task;
+use qurl_core::{
+    actions::{
+        self, AppAction,
+        Interaction::{Key, Mod},
+    },
+    middlewares::recorder::{self, Recorder},
+    state::{App, Edits, Pane},
+    AppStore,
+};
+use std::{fs, path::PathBuf};
+use tui::layout::Rect;
+
+/// Resizes, opens the cookies panel, moves the mouse, switches to the
+/// response panel and undoes the switch
+const SESSION: &str = r#"{"at":0,"action":{"Resize":{"x":0,"y":0,"width":120,"height":40}}}
+{"at":10,"action":{"Interaction":[{"Ctrl":{"Char":"k"}}]}}
+{"at":20,"action":{"Interaction":[{"Any":{"MouseMove":[10,5]}}]}}
+{"at":30,"action":{"Interaction":[{"Ctrl":{"Char":"r"}}]}}
+{"at":40,"action":{"Interaction":[{"Ctrl":{"Char":"z"}}]}}
+{"at":50,"action":{"Resize":{"x":0,"y":0,"width":80,"height":24}}}
+"#;
+
+fn session(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("qurl-{}-{}.jsonl", name, std::process::id()));
+    fs::write(&path, SESSION).unwrap();
+    path
+}
+
+/// `(size, panel, mouse position)` after replaying `path` into a fresh store
+async fn replay(path: &PathBuf) -> (Rect, Option<Pane>, (u16, u16)) {
+    let store = AppStore::new(App::default());
+    let _handlers = actions::register(&store).await;
+    assert_eq!(recorder::replay(&store, path, false).await.unwrap(), 6);
+    let app = store.state.read().await;
+    (app.size, app.panel, app.mouse_pos)
+}
+
+#[test]
+fn replays_a_session_deterministically() {
+    let path = session("replay");
+    let (first, second) = task::block_on(async { (replay(&path).await, replay(&path).await) });
+    fs::remove_file(&path).unwrap();
+    assert_eq!(first, second);
+    // undo brings the panel back but leaves the size and the pointer alone
+    assert_eq!(
+        first,
+        (Rect::new(0, 0, 80, 24), Some(Pane::Cookies), (10, 5))
+    );
+}
+
+#[test]
+fn a_recorded_session_replays_to_the_same_state() {
+    let path = std::env::temp_dir().join(format!("qurl-record-{}.jsonl", std::process::id()));
+    let key = |m: Mod| AppAction::Interaction(vec![m]);
+    let actions = vec![
+        AppAction::Resize(Rect::new(0, 0, 100, 30)),
+        key(Mod::Ctrl(Key::Char('k'))),
+        key(Mod::Any(Key::MouseMove(7, 3))),
+        // not an input so it is left out of the file
+        AppAction::Rendered,
+        key(Mod::Ctrl(Key::Char('r'))),
+        key(Mod::Any(Key::Char('.'))),
+        key(Mod::Ctrl(Key::Char('z'))),
+        AppAction::Tick,
+    ];
+    let (recorded, replayed) = task::block_on(async {
+        let store = AppStore::new(App::default());
+        let _handlers = actions::register(&store).await;
+        let recorder = Recorder::create(&path).unwrap();
+        store.middleware(recorder).await.forget();
+        for action in actions {
+            store.dispatch_and_settle(action).await;
+        }
+        let recorded = summary(&*store.state.read().await);
+
+        let store = AppStore::new(App::default());
+        let _handlers = actions::register(&store).await;
+        assert_eq!(recorder::replay(&store, &path, false).await.unwrap(), 7);
+        let replayed = summary(&*store.state.read().await);
+        (recorded, replayed)
+    });
+    fs::remove_file(&path).unwrap();
+    assert_eq!(recorded, replayed);
+}
+
+fn summary(app: &App) -> (Edits, Rect, (u16, u16)) {
+    (app.edits(), app.size, app.mouse_pos)
+}